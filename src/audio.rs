@@ -1,81 +1,976 @@
+#[cfg(target_os = "linux")]
+mod alsa;
+mod dry_run;
+mod mock;
+#[cfg(target_os = "linux")]
+mod pipewire;
+#[cfg(target_os = "linux")]
+mod pulse;
+#[cfg(windows)]
+mod wasapi;
+
+use std::any::Any;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+
 use anyhow::{Context, Result};
-use windows::{Win32::Media::Audio::Endpoints::*, Win32::Media::Audio::*, Win32::System::Com::*};
+pub use dry_run::DryRunBackend;
+pub use mock::MockBackend;
+#[cfg(test)]
+pub use mock::Operation;
+
+use crate::config::{BackendKind, Config};
+use crate::volume::{Ramp, VolumeChange, VolumeLevel};
+
+/// Kind of audio devices a backend controls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// Microphones and other capture devices
+    #[default]
+    Capture,
+    /// Speakers, headphones and other output devices
+    Render,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Capture => "capture",
+            Direction::Render => "output",
+        })
+    }
+}
+
+/// Availability of a capture device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceState {
+    Active,
+    Disabled,
+    Unplugged,
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceState::Active => "active",
+            DeviceState::Disabled => "disabled",
+            DeviceState::Unplugged => "unplugged",
+        })
+    }
+}
+
+/// Purpose a device can be the system default for
+///
+/// Windows keeps a separate default per role; other platforms have a single
+/// default device that serves all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Console,
+    Multimedia,
+    Communications,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Console, Role::Multimedia, Role::Communications];
+}
 
-/// Simple audio controller for microphone volume management
-pub struct AudioController;
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Console => "console",
+            Role::Multimedia => "multimedia",
+            Role::Communications => "communications",
+        })
+    }
+}
+
+/// Capture device as reported by a volume backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// Stable backend-specific identifier
+    pub id: String,
+    /// Human-readable device name
+    pub name: String,
+    pub state: DeviceState,
+    /// Current volume (0.0 - 1.0), if it could be read
+    pub volume: Option<f32>,
+    /// Current mute state, if it could be read
+    pub muted: Option<bool>,
+    /// Roles this device is the system default for
+    pub default_for: Vec<Role>,
+}
+
+impl DeviceInfo {
+    /// Whether this is the device the backend operates on by default
+    pub fn is_default(&self) -> bool {
+        self.default_for.contains(&Role::Console)
+    }
 
-impl AudioController {
-    fn get_device_enumerator() -> Result<IMMDeviceEnumerator> {
-        unsafe {
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
-                .context("Failed to create device enumerator")
+    /// Whether the friendly name matches `pattern` (see `name_matches`)
+    pub fn name_matches(&self, pattern: &str) -> bool {
+        name_matches(&self.name, pattern)
+    }
+}
+
+/// Whether `name` matches `pattern`, ignoring case
+///
+/// `*` matches any run of characters; a pattern without `*` matches any name
+/// containing it.
+pub fn name_matches(name: &str, pattern: &str) -> bool {
+    let name = name.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if !pattern.contains('*') {
+        return name.contains(&pattern);
+    }
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let last = parts.next_back().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
         }
     }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Capture stream an application has open, with its own volume on top of the
+/// device volume
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    /// Backend-specific identifier, valid while the stream is open
+    pub id: String,
+    /// Name of the application that opened the stream
+    pub application: String,
+    /// Stream volume (0.0 - 1.0)
+    pub volume: f32,
+    /// Stream mute state, if it could be read
+    pub muted: Option<bool>,
+}
+
+/// Who made a reported volume change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// A write of this process
+    Own,
+    /// Another application, the user or the driver
+    External,
+}
+
+/// Change reported through `VolumeBackend::watch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VolumeEvent {
+    /// The volume or mute state of a device or stream changed
+    VolumeChanged {
+        /// ID of the device, when the notification names it
+        device: Option<String>,
+        origin: Origin,
+    },
+    /// A device was plugged in or enabled
+    DeviceAdded {
+        /// ID of the device, when the notification names it
+        device: Option<String>,
+    },
+    /// A device was unplugged, disabled or removed
+    DeviceRemoved {
+        /// ID of the device, when the notification names it
+        device: Option<String>,
+    },
+    /// The default device of a role changed
+    DefaultChanged,
+}
+
+impl VolumeEvent {
+    /// Whether the set of devices or their default roles changed
+    pub fn is_device_change(&self) -> bool {
+        !matches!(self, VolumeEvent::VolumeChanged { .. })
+    }
+
+    /// Whether this is a volume change this process made
+    pub fn is_own(&self) -> bool {
+        matches!(
+            self,
+            VolumeEvent::VolumeChanged {
+                origin: Origin::Own,
+                ..
+            }
+        )
+    }
+}
+
+/// Writes this process made recently, for backends whose notifications do
+/// not tell who made a change
+///
+/// A change to a device written within `OWN_WRITE_WINDOW` is taken as ours;
+/// an external change that close to one of our writes is taken as ours too,
+/// which callers catch up with by checking once the window is over.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default)]
+struct OwnWrites(std::sync::Arc<std::sync::Mutex<Vec<OwnWrite>>>);
+
+/// Write recorded in `OwnWrites`
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct OwnWrite {
+    /// Device written, or `None` when the backend cannot name it
+    device: Option<String>,
+    started: Instant,
+}
+
+/// How long after it started a write's notifications are attributed to it
+/// by backends whose notifications do not tell who made a change
+pub const OWN_WRITE_WINDOW: Duration = Duration::from_secs(1);
+
+#[cfg(target_os = "linux")]
+impl OwnWrites {
+    /// Note a write to `device` (any device when `None`) that is about to
+    /// start
+    fn record(&self, device: Option<&str>) {
+        let mut writes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        writes.retain(|write| write.started.elapsed() < OWN_WRITE_WINDOW);
+        writes.push(OwnWrite {
+            device: device.map(str::to_string),
+            started: Instant::now(),
+        });
+    }
 
-    fn get_default_microphone(enumerator: &IMMDeviceEnumerator) -> Result<IMMDevice> {
-        unsafe {
-            enumerator
-                .GetDefaultAudioEndpoint(eCapture, eConsole)
-                .context("Failed to get default microphone")
+    /// `event` with its origin set to `Origin::Own` if it follows one of our
+    /// writes to the same device, where both name one
+    fn tag(&self, event: VolumeEvent) -> VolumeEvent {
+        let VolumeEvent::VolumeChanged { device, .. } = event else {
+            return event;
+        };
+        let writes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let own = writes.iter().any(|write| {
+            write.started.elapsed() < OWN_WRITE_WINDOW
+                && (write.device.is_none() || device.is_none() || write.device == device)
+        });
+        VolumeEvent::VolumeChanged {
+            device,
+            origin: if own { Origin::Own } else { Origin::External },
         }
     }
+}
+
+/// Registration for change notifications; they stop when it is dropped
+pub struct Watch {
+    _registration: Box<dyn Any>,
+}
 
-    fn get_volume_control(device: &IMMDevice) -> Result<IAudioEndpointVolume> {
-        unsafe {
-            device
-                .Activate(CLSCTX_ALL, None)
-                .context("Failed to activate audio endpoint volume")
+impl Watch {
+    /// Watch that lasts as long as `registration` (a callback registration,
+    /// a monitor process) is kept
+    pub fn new(registration: impl Any) -> Self {
+        Self {
+            _registration: Box::new(registration),
         }
     }
+}
 
-    /// Get current microphone volume (0.0 - 1.0)
-    pub fn get_current_volume() -> Result<f32> {
-        let enumerator = Self::get_device_enumerator()?;
-        let device = Self::get_default_microphone(&enumerator)?;
-        let volume = Self::get_volume_control(&device)?;
+/// Capture device an operation acts on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// The system default capture device
+    Default,
+    /// The device with this backend-specific ID (`DeviceInfo::id`)
+    Id(String),
+}
 
-        unsafe {
-            volume
-                .GetMasterVolumeLevelScalar()
-                .context("Failed to get volume level")
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Default => f.write_str("default device"),
+            Endpoint::Id(id) => write!(f, "device '{}'", id),
         }
     }
+}
 
-    /// Set microphone volume (0.0 - 1.0)
-    pub fn set_volume(target_volume: f32) -> Result<()> {
-        if !(0.0..=1.0).contains(&target_volume) {
-            anyhow::bail!("Volume must be between 0.0 and 1.0");
+/// Present devices a selector names: the device with that exact ID, or else
+/// every device whose friendly name matches it as a pattern (see
+/// `DeviceInfo::name_matches`)
+///
+/// Devices that are not active count as absent.
+pub fn matching_devices<'a>(devices: &'a [DeviceInfo], selector: &str) -> Vec<&'a DeviceInfo> {
+    let by_id: Vec<&DeviceInfo> = devices.iter().filter(|d| d.id == selector).collect();
+    let candidates = if by_id.is_empty() {
+        devices
+            .iter()
+            .filter(|d| d.name_matches(selector))
+            .collect()
+    } else {
+        by_id
+    };
+    candidates
+        .into_iter()
+        .filter(|d| d.state == DeviceState::Active)
+        .collect()
+}
+
+/// Find the single present device a `device` selector names
+///
+/// Returns `None` when no present device matches, and fails when the pattern
+/// matches more than one.
+pub fn find_device(devices: &[DeviceInfo], selector: &str) -> Result<Option<DeviceInfo>> {
+    match matching_devices(devices, selector).as_slice() {
+        [] => Ok(None),
+        [device] => Ok(Some((*device).clone())),
+        several => {
+            let names: Vec<String> = several
+                .iter()
+                .map(|d| format!("{} ({})", d.name, d.id))
+                .collect();
+            anyhow::bail!(
+                "Device '{}' matches several devices: {}; use a device ID instead",
+                selector,
+                names.join(", ")
+            )
         }
+    }
+}
 
-        let enumerator = Self::get_device_enumerator()?;
-        let device = Self::get_default_microphone(&enumerator)?;
-        let volume = Self::get_volume_control(&device)?;
+/// Gain range of a volume control
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeRange {
+    pub min_db: f32,
+    pub max_db: f32,
+    /// Smallest gain change the control makes; `None` for software volume
+    pub step_db: Option<f32>,
+}
 
-        unsafe {
-            volume
-                .SetMasterVolumeLevelScalar(target_volume, std::ptr::null())
-                .context("Failed to set volume level")?;
+impl VolumeRange {
+    /// Reject gains outside the range
+    pub fn check(&self, db: f32) -> Result<()> {
+        if db.is_nan() || db < self.min_db || db > self.max_db {
+            anyhow::bail!(
+                "Volume must be between {:.2} dB and {:.2} dB",
+                self.min_db,
+                self.max_db
+            );
         }
+        Ok(())
+    }
+}
 
+impl fmt::Display for VolumeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} dB to {:.2} dB", self.min_db, self.max_db)?;
+        if let Some(step) = self.step_db {
+            write!(f, ", step {:.2} dB", step)?;
+        }
         Ok(())
     }
 }
 
+/// Volume of one channel of a capture device
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelVolume {
+    /// Channel name as the audio system reports it ("front-left", "Front
+    /// Left"), or its position when it has none
+    pub name: String,
+    /// Volume scalar (0.0 - 1.0)
+    pub volume: f32,
+    /// Gain in dB, when the control reports one
+    pub db: Option<f32>,
+}
+
+impl fmt::Display for ChannelVolume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:.0}%", self.name, self.volume * 100.0)?;
+        if let Some(db) = self.db {
+            write!(f, " ({:.2} dB)", db)?;
+        }
+        Ok(())
+    }
+}
+
+/// Range of the cubic software volume of PulseAudio and PipeWire, where the
+/// scalar is the cube root of the linear gain and 100% is unity gain
+#[cfg(target_os = "linux")]
+const SOFTWARE_VOLUME_RANGE: VolumeRange = VolumeRange {
+    min_db: f32::NEG_INFINITY,
+    max_db: 0.0,
+    step_db: None,
+};
+
+/// Gain of a cubic software volume scalar
+#[cfg(target_os = "linux")]
+fn software_volume_to_db(volume: f32) -> f32 {
+    60.0 * volume.log10()
+}
+
+/// Cubic software volume scalar of a gain
+#[cfg(target_os = "linux")]
+fn db_to_software_volume(db: f32) -> f32 {
+    10f32.powf(db / 60.0)
+}
+
+/// Optional operations supported by a volume backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Mute state can be read and changed
+    pub mute: bool,
+    /// `list_devices` enumerates every capture device, not only the default
+    pub device_list: bool,
+    /// Per-application streams can be listed and their volume changed
+    pub streams: bool,
+}
+
+/// Access to the microphone volume of a platform audio system
+///
+/// Operations act on the capture device named by an `Endpoint`. Volume
+/// levels are scalars in the 0.0 - 1.0 range.
+pub trait VolumeBackend {
+    /// Short backend name used in messages
+    fn name(&self) -> &'static str;
+
+    /// Get current microphone volume (0.0 - 1.0)
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32>;
+
+    /// Set microphone volume (0.0 - 1.0)
+    fn set_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<()>;
+
+    /// Whether the microphone is muted
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool>;
+
+    /// Get current microphone volume in dB
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32>;
+
+    /// Set microphone volume in dB, within `volume_range`
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()>;
+
+    /// Gain range of the microphone volume control
+    fn volume_range(&self, endpoint: &Endpoint) -> Result<VolumeRange>;
+
+    /// Scalar distance between two adjacent settings of the microphone
+    /// volume control, when the backend knows it; a control this coarse
+    /// rounds what it is given by up to this much
+    fn volume_step(&self, _endpoint: &Endpoint) -> Result<Option<f32>> {
+        Ok(None)
+    }
+
+    /// Set the microphone volume to `level`, whichever unit it is in
+    fn set_level(&self, endpoint: &Endpoint, level: VolumeLevel) -> Result<()> {
+        match level {
+            VolumeLevel::Scalar(volume) => self.set_volume(endpoint, volume),
+            VolumeLevel::Db(db) => self.set_volume_db(endpoint, db),
+        }
+    }
+
+    /// Level `change` leads to from the current volume; steps are clamped to
+    /// the valid range (0 - 100%, or the control's dB range)
+    fn resolve_change(&self, endpoint: &Endpoint, change: VolumeChange) -> Result<VolumeLevel> {
+        match change {
+            VolumeChange::To(level) => Ok(level),
+            VolumeChange::By(VolumeLevel::Scalar(step)) => {
+                let current = self.get_volume(endpoint)?;
+                Ok(VolumeLevel::Scalar((current + step).clamp(0.0, 1.0)))
+            }
+            VolumeChange::By(VolumeLevel::Db(step)) => {
+                let current = self.get_volume_db(endpoint)?;
+                let range = self.volume_range(endpoint)?;
+                Ok(VolumeLevel::Db(
+                    (current + step).clamp(range.min_db, range.max_db),
+                ))
+            }
+        }
+    }
+
+    /// Volume of every channel of the microphone, in channel order
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>>;
+
+    /// Set the volume of every channel (0.0 - 1.0); `volumes` holds one
+    /// level per channel, in channel order
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()>;
+
+    /// Mute or unmute the microphone
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()>;
+
+    /// Enumerate capture devices with their state, volume and default roles
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>>;
+
+    /// Enumerate the capture streams applications have open
+    fn list_streams(&self) -> Result<Vec<StreamInfo>>;
+
+    /// Set the volume (0.0 - 1.0) of the stream with this `StreamInfo::id`
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()>;
+
+    /// Send a `VolumeEvent` on `events` whenever a device or stream of this
+    /// backend changes, or a device comes or goes, until the returned `Watch`
+    /// is dropped
+    ///
+    /// Changes this process makes are reported too.
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch>;
+
+    /// Optional operations this backend supports
+    fn capabilities(&self) -> Capabilities;
+}
+
+/// Time between two steps of a ramp
+const RAMP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Smallest distance from the volume a ramp set last beyond which it counts
+/// as changed by someone else; backends round what they are given, coarse
+/// controls by up to their step
+const RAMP_TOLERANCE: f32 = 0.01;
+const RAMP_DB_TOLERANCE: f32 = 0.2;
+
+/// Move the volume of `endpoint` to `level` in steps along `ramp`, in the
+/// unit of `level`
+///
+/// Returns `false` when something else changed the volume during the ramp,
+/// which is then left where they put it.
+pub fn ramp_level(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+    ramp: &Ramp,
+) -> Result<bool> {
+    ramp_level_with(backend, endpoint, level, ramp, std::thread::sleep)
+}
+
+fn ramp_level_with(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+    ramp: &Ramp,
+    mut pause: impl FnMut(Duration),
+) -> Result<bool> {
+    let read = || match level {
+        VolumeLevel::Scalar(_) => backend.get_volume(endpoint),
+        VolumeLevel::Db(_) => backend.get_volume_db(endpoint),
+    };
+    let tolerance = match level {
+        VolumeLevel::Scalar(_) => backend
+            .volume_step(endpoint)?
+            .map_or(RAMP_TOLERANCE, |step| step.max(RAMP_TOLERANCE)),
+        VolumeLevel::Db(_) => {
+            let step = backend.volume_range(endpoint)?.step_db.unwrap_or(0.0);
+            step.max(RAMP_DB_TOLERANCE)
+        }
+    };
+    let target = level.value();
+
+    let start = read()?;
+    let steps = (ramp.duration().as_millis() / RAMP_INTERVAL.as_millis()).max(1) as u32;
+    if !start.is_finite() || steps == 1 {
+        // No gain to start from at a software volume of 0, or no time to ramp
+        backend.set_level(endpoint, level)?;
+        return Ok(true);
+    }
+
+    let interval = ramp.duration() / steps;
+    let mut expected = start;
+    for step in 1..=steps {
+        if step > 1 {
+            pause(interval);
+            if (read()? - expected).abs() > tolerance {
+                return Ok(false);
+            }
+        }
+        expected = start + (target - start) * ramp.curve.at(step as f32 / steps as f32);
+        backend.set_level(endpoint, level.with_value(expected))?;
+    }
+    Ok(true)
+}
+
+/// Native backend for the current platform, controlling devices of
+/// `direction`
+#[cfg_attr(windows, allow(unused_variables))]
+pub fn default_backend(config: &Config, direction: Direction) -> Box<dyn VolumeBackend> {
+    #[cfg(windows)]
+    return Box::new(wasapi::AudioController::new(direction));
+
+    #[cfg(target_os = "linux")]
+    if pipewire::AudioController::is_available() {
+        Box::new(pipewire::AudioController::new(direction))
+    } else if pulse::AudioController::is_available() {
+        Box::new(pulse::AudioController::new(direction))
+    } else {
+        Box::new(alsa::AudioController::new(&config.alsa, direction))
+    }
+}
+
+/// Backend of the given kind for devices of `direction`, configured from
+/// `config`
+///
+/// The mock backend is scripted through the `MIC_VOLUME_CONTROL_MOCK`
+/// environment variable, or `MIC_VOLUME_CONTROL_MOCK_OUTPUT` for output
+/// devices (see `MockBackend::from_spec`).
+pub fn create_backend(
+    kind: BackendKind,
+    config: &Config,
+    direction: Direction,
+) -> Result<Box<dyn VolumeBackend>> {
+    match kind {
+        BackendKind::Auto => Ok(default_backend(config, direction)),
+        #[cfg(target_os = "linux")]
+        BackendKind::Pulse => Ok(Box::new(pulse::AudioController::new(direction))),
+        #[cfg(target_os = "linux")]
+        BackendKind::Pipewire => Ok(Box::new(pipewire::AudioController::new(direction))),
+        #[cfg(target_os = "linux")]
+        BackendKind::Alsa => Ok(Box::new(alsa::AudioController::new(
+            &config.alsa,
+            direction,
+        ))),
+        BackendKind::Mock => {
+            let (variable, default_spec) = match direction {
+                Direction::Capture => ("MIC_VOLUME_CONTROL_MOCK", ""),
+                Direction::Render => ("MIC_VOLUME_CONTROL_MOCK_OUTPUT", "Mock Speakers=50"),
+            };
+            let script = std::env::var(variable).unwrap_or_default();
+            let script = if script.is_empty() {
+                default_spec.to_string()
+            } else {
+                script
+            };
+            let backend = MockBackend::from_spec(&script)
+                .with_context(|| format!("Invalid {} script", variable))?;
+            Ok(Box::new(backend))
+        }
+        #[allow(unreachable_patterns)]
+        _ => anyhow::bail!("The {} backend is not available on this platform", kind),
+    }
+}
+
+/// Reject volume scalars outside 0.0 - 1.0
+fn check_volume_range(volume: f32) -> Result<()> {
+    if !(0.0..=1.0).contains(&volume) {
+        anyhow::bail!("Volume must be between 0.0 and 1.0");
+    }
+    Ok(())
+}
+
+/// Reject per-channel levels that are out of range or do not cover exactly
+/// the `channels` of the device
+fn check_channel_volumes(volumes: &[f32], channels: usize) -> Result<()> {
+    if volumes.len() != channels {
+        anyhow::bail!(
+            "The device has {} channels but {} channel levels were given",
+            channels,
+            volumes.len()
+        );
+    }
+    volumes
+        .iter()
+        .try_for_each(|&volume| check_volume_range(volume))
+}
+
+/// Run a command-line audio utility and return its standard output
+///
+/// The C locale is forced so that the output can be parsed regardless of the
+/// user's language.
+#[cfg(target_os = "linux")]
+fn run_tool(program: &str, package: &str, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .with_context(|| format!("Failed to run {} (is {} installed?)", program, package))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Child process of a monitoring utility, killed when dropped
+#[cfg(target_os = "linux")]
+struct MonitorProcess(std::process::Child);
+
+#[cfg(target_os = "linux")]
+impl Drop for MonitorProcess {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start a long-running monitoring utility, in the C locale like `run_tool`,
+/// with its standard output piped
+#[cfg(target_os = "linux")]
+fn spawn_monitor(
+    program: &str,
+    package: &str,
+    args: &[&str],
+) -> Result<(MonitorProcess, std::process::ChildStdout)> {
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {} (is {} installed?)", program, package))?;
+    let stdout = child.stdout.take().context("Missing monitor output")?;
+    Ok((MonitorProcess(child), stdout))
+}
+
+/// Start a monitoring utility and send the events `parse` finds in its
+/// output, one line at a time, on `events`
+///
+/// The events stop when the utility exits, which drops the sender, and the
+/// utility is stopped when the returned `Watch` is dropped.
+#[cfg(target_os = "linux")]
+fn watch_tool(
+    program: &str,
+    package: &str,
+    args: &[&str],
+    events: Sender<VolumeEvent>,
+    parse: impl Fn(&str) -> Option<VolumeEvent> + Send + 'static,
+) -> Result<Watch> {
+    use std::io::BufRead;
+
+    let (process, stdout) = spawn_monitor(program, package, args)?;
+    std::thread::spawn(move || {
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(event) = parse(&line)
+                && events.send(event).is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    Ok(Watch::new(process))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::volume::Curve;
 
     #[test]
     fn test_invalid_volume_range() {
-        assert!(AudioController::set_volume(1.5).is_err());
-        assert!(AudioController::set_volume(-0.1).is_err());
+        let backend = default_backend(&Config::default(), Direction::Capture);
+        assert!(backend.set_volume(&Endpoint::Default, 1.5).is_err());
+        assert!(backend.set_volume(&Endpoint::Default, -0.1).is_err());
+    }
+
+    #[test]
+    fn test_volume_range() {
+        let range = VolumeRange {
+            min_db: -17.25,
+            max_db: 30.0,
+            step_db: Some(0.75),
+        };
+        assert!(range.check(-17.25).is_ok());
+        assert!(range.check(12.5).is_ok());
+        assert!(range.check(30.5).is_err());
+        assert!(range.check(f32::NAN).is_err());
+        assert_eq!(range.to_string(), "-17.25 dB to 30.00 dB, step 0.75 dB");
+    }
+
+    #[test]
+    fn test_resolve_change() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let endpoint = Endpoint::Default;
+        let resolve = |change: &str| {
+            backend
+                .resolve_change(&endpoint, change.parse().unwrap())
+                .unwrap()
+        };
+
+        assert_eq!(resolve("80"), VolumeLevel::Scalar(0.8));
+        assert_eq!(resolve("+10"), VolumeLevel::Scalar(0.6));
+        assert_eq!(resolve("+60"), VolumeLevel::Scalar(1.0));
+        assert_eq!(resolve("-75"), VolumeLevel::Scalar(0.0));
+        assert_eq!(resolve("-3dB"), VolumeLevel::Db(-33.0));
+        assert_eq!(resolve("+40dB"), VolumeLevel::Db(0.0));
+        assert_eq!(resolve("-40dB"), VolumeLevel::Db(-60.0));
+    }
+
+    #[test]
+    fn test_ramp_level() {
+        let backend = MockBackend::new().with_device("Mic", 0.4, false);
+        let endpoint = Endpoint::Default;
+        let ramp = Ramp {
+            duration_ms: 200,
+            curve: Curve::Linear,
+        };
+        let mut volumes = Vec::new();
+        assert!(
+            ramp_level_with(
+                &backend,
+                &endpoint,
+                VolumeLevel::Scalar(0.8),
+                &ramp,
+                |pause| {
+                    assert_eq!(pause, Duration::from_millis(50));
+                    volumes.push(backend.get_volume(&endpoint).unwrap());
+                }
+            )
+            .unwrap()
+        );
+        let close = |volumes: &[f32], expected: &[f32]| {
+            volumes.len() == expected.len()
+                && volumes
+                    .iter()
+                    .zip(expected)
+                    .all(|(a, b)| (a - b).abs() < 1e-4)
+        };
+        assert!(close(&volumes, &[0.5, 0.6, 0.7]), "{:?}", volumes);
+        assert_eq!(backend.get_volume(&endpoint).unwrap(), 0.8);
+
+        // In dB, along the curve
+        let ramp = Ramp {
+            duration_ms: 100,
+            curve: Curve::EaseIn,
+        };
+        let mut volumes = Vec::new();
+        ramp_level_with(&backend, &endpoint, VolumeLevel::Db(-36.0), &ramp, |_| {
+            volumes.push(backend.get_volume_db(&endpoint).unwrap());
+        })
+        .unwrap();
+        assert!(close(&volumes, &[-18.0]), "{:?}", volumes);
+        assert!(close(
+            &[backend.get_volume_db(&endpoint).unwrap()],
+            &[-36.0]
+        ));
+
+        // Someone else moving the volume stops the ramp where they put it
+        let mut pauses = 0;
+        let ramp = Ramp {
+            duration_ms: 500,
+            curve: Curve::Linear,
+        };
+        assert!(
+            !ramp_level_with(&backend, &endpoint, VolumeLevel::Scalar(1.0), &ramp, |_| {
+                pauses += 1;
+                if pauses == 3 {
+                    backend.external().set_volume(&endpoint, 0.2).unwrap();
+                }
+            })
+            .unwrap()
+        );
+        assert_eq!(pauses, 3);
+        assert_eq!(backend.get_volume(&endpoint).unwrap(), 0.2);
+
+        // A coarse control rounding every step is not someone else
+        let backend = MockBackend::from_spec("Mic=10,steps=31").unwrap();
+        let ramp = Ramp {
+            duration_ms: 1000,
+            curve: Curve::Linear,
+        };
+        assert!(
+            ramp_level_with(
+                &backend,
+                &endpoint,
+                VolumeLevel::Scalar(0.95),
+                &ramp,
+                |_| {}
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_software_volume_db() {
+        assert_eq!(software_volume_to_db(1.0), 0.0);
+        assert!((software_volume_to_db(0.5) + 18.06).abs() < 0.01);
+        assert_eq!(software_volume_to_db(0.0), f32::NEG_INFINITY);
+        assert!((db_to_software_volume(-18.06) - 0.5).abs() < 0.001);
+        assert!(SOFTWARE_VOLUME_RANGE.check(-120.0).is_ok());
+        assert!(SOFTWARE_VOLUME_RANGE.check(3.0).is_err());
+    }
+
+    fn device(id: &str, name: &str, state: DeviceState) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            state,
+            volume: None,
+            muted: None,
+            default_for: Vec::new(),
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_own_writes() {
+        let changed = |device: Option<&str>| VolumeEvent::VolumeChanged {
+            device: device.map(str::to_string),
+            origin: Origin::External,
+        };
+        let writes = OwnWrites::default();
+        assert!(!writes.tag(changed(Some("mic"))).is_own());
+
+        writes.record(Some("mic"));
+        assert!(writes.tag(changed(Some("mic"))).is_own());
+        assert!(writes.tag(changed(None)).is_own());
+        assert!(!writes.tag(changed(Some("webcam"))).is_own());
+        assert_eq!(
+            writes.tag(VolumeEvent::DefaultChanged),
+            VolumeEvent::DefaultChanged
+        );
+
+        // Writes of clones count, as the watch thread holds one
+        writes.clone().record(None);
+        assert!(writes.tag(changed(Some("webcam"))).is_own());
+    }
+
+    #[test]
+    fn test_name_matches() {
+        let yeti = device("1", "Yeti Stereo Microphone", DeviceState::Active);
+        assert!(yeti.name_matches("yeti"));
+        assert!(yeti.name_matches("Yeti*Microphone"));
+        assert!(yeti.name_matches("*stereo*"));
+        assert!(yeti.name_matches("*"));
+        assert!(!yeti.name_matches("Yeti*Headset"));
+        assert!(!yeti.name_matches("Stereo*"));
+        assert!(!yeti.name_matches("*Yeti"));
+        assert!(!yeti.name_matches("Microphone*Microphone"));
+    }
+
+    #[test]
+    fn test_find_device() {
+        let devices = [
+            device(
+                "{0.0.1}.{a}",
+                "Microphone Array (Realtek)",
+                DeviceState::Active,
+            ),
+            device(
+                "{0.0.1}.{b}",
+                "Headset Microphone (USB)",
+                DeviceState::Active,
+            ),
+            device("{0.0.1}.{c}", "Yeti Microphone", DeviceState::Unplugged),
+        ];
+
+        let usb = find_device(&devices, "headset").unwrap().unwrap();
+        assert_eq!(usb.id, "{0.0.1}.{b}");
+        let array = find_device(&devices, "{0.0.1}.{a}").unwrap().unwrap();
+        assert_eq!(array.name, "Microphone Array (Realtek)");
+
+        assert!(find_device(&devices, "yeti").unwrap().is_none());
+        assert!(find_device(&devices, "{0.0.1}.{c}").unwrap().is_none());
+        assert!(find_device(&devices, "webcam").unwrap().is_none());
+        assert!(find_device(&devices, "microphone").is_err());
     }
 
     // Note: The following tests require actual audio hardware and may fail in CI
     #[test]
     #[ignore]
     fn test_get_volume() {
-        let result = AudioController::get_current_volume();
+        let result =
+            default_backend(&Config::default(), Direction::Capture).get_volume(&Endpoint::Default);
         if let Ok(volume) = result {
             assert!((0.0..=1.0).contains(&volume));
         }
@@ -84,12 +979,22 @@ mod tests {
     #[test]
     #[ignore]
     fn test_set_volume() {
-        let result = AudioController::set_volume(0.5);
+        let backend = default_backend(&Config::default(), Direction::Capture);
+        let result = backend.set_volume(&Endpoint::Default, 0.5);
         if result.is_ok() {
             std::thread::sleep(std::time::Duration::from_millis(100));
-            if let Ok(volume) = AudioController::get_current_volume() {
+            if let Ok(volume) = backend.get_volume(&Endpoint::Default) {
                 assert!((volume - 0.5).abs() < 0.02);
             }
         }
     }
+
+    #[test]
+    #[ignore]
+    fn test_list_devices() {
+        if let Ok(devices) = default_backend(&Config::default(), Direction::Capture).list_devices()
+        {
+            assert!(devices.iter().filter(|device| device.is_default()).count() <= 1);
+        }
+    }
 }
@@ -0,0 +1,771 @@
+use std::sync::{OnceLock, mpsc::Sender};
+
+use anyhow::{Context, Result};
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, DeviceState, Direction, Endpoint, Origin, OwnWrites,
+    Role, StreamInfo, VolumeBackend, VolumeEvent, VolumeRange, Watch, check_channel_volumes,
+    check_volume_range, run_tool, watch_tool,
+};
+use crate::config::AlsaConfig;
+
+/// PCM device addressed when no card is configured; it follows the sound
+/// server when one is running
+const DEFAULT_DEVICE: &str = "default";
+
+/// Simple mixer controls tried, in order, when none is configured
+const CAPTURE_CONTROLS: &[&str] = &["Capture", "Mic"];
+
+/// Simple mixer controls tried, in order, for output volume when none is
+/// configured
+const PLAYBACK_CONTROLS: &[&str] = &["Master", "PCM", "Speaker", "Headphone"];
+
+/// Stream name used by amixer for `direction`, as in `Front Left: Capture`
+fn stream(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Capture => "Capture",
+        Direction::Render => "Playback",
+    }
+}
+
+/// Microphone or output volume control through the ALSA simple mixer
+/// (`amixer`)
+pub struct AudioController {
+    /// Card index or name; the `default` device when unset
+    card: Option<String>,
+    /// Configured control name; discovered from `CAPTURE_CONTROLS` (or
+    /// `PLAYBACK_CONTROLS`) when unset
+    control: Option<String>,
+    direction: Direction,
+    resolved_control: OnceLock<String>,
+    /// Value events do not say who changed the control
+    own_writes: OwnWrites,
+}
+
+impl AudioController {
+    pub fn new(settings: &AlsaConfig, direction: Direction) -> Self {
+        let control = match direction {
+            Direction::Capture => &settings.control,
+            Direction::Render => &settings.playback_control,
+        };
+        Self {
+            card: settings.card.clone(),
+            control: control.clone(),
+            direction,
+            resolved_control: OnceLock::new(),
+            own_writes: OwnWrites::default(),
+        }
+    }
+
+    /// Word selecting the stream in `amixer sset`; it keeps controls such as
+    /// "Mic" from changing both streams
+    fn sset_stream(&self) -> &'static str {
+        match self.direction {
+            Direction::Capture => "capture",
+            Direction::Render => "playback",
+        }
+    }
+
+    fn amixer_args<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
+        let mut full_args = match &self.card {
+            Some(card) => vec!["-c", card.as_str()],
+            None => vec!["-D", DEFAULT_DEVICE],
+        };
+        full_args.extend_from_slice(args);
+        full_args
+    }
+
+    fn run_amixer(&self, args: &[&str]) -> Result<String> {
+        run_tool("amixer", "alsa-utils", &self.amixer_args(args))
+    }
+
+    /// Run an amixer command that changes the control
+    fn write(&self, args: &[&str]) -> Result<String> {
+        self.own_writes.record(None);
+        self.run_amixer(args)
+    }
+
+    /// Control named by an `amixer events` value line, e.g. `event value:
+    /// 'Capture',0`
+    fn parse_event(line: &str) -> Option<&str> {
+        let rest = line.trim().strip_prefix("event value: '")?;
+        let (name, _) = rest.rsplit_once("',")?;
+        Some(name)
+    }
+
+    /// Names from `amixer scontrols` output
+    fn parse_controls(output: &str) -> Vec<String> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix("Simple mixer control '")?;
+                let (name, _) = rest.rsplit_once("',")?;
+                Some(name.to_string())
+            })
+            .collect()
+    }
+
+    /// Pick the configured control, or the first known control for
+    /// `direction` the card exposes
+    fn find_control(
+        available: &[String],
+        configured: Option<&str>,
+        direction: Direction,
+    ) -> Result<String> {
+        if let Some(configured) = configured {
+            return available
+                .iter()
+                .find(|name| name.as_str() == configured)
+                .cloned()
+                .with_context(|| format!("Mixer control '{}' not found", configured));
+        }
+
+        let (candidates, setting) = match direction {
+            Direction::Capture => (CAPTURE_CONTROLS, "control"),
+            Direction::Render => (PLAYBACK_CONTROLS, "playback_control"),
+        };
+        candidates
+            .iter()
+            .find_map(|candidate| available.iter().find(|name| name == candidate))
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "No {} control found (tried {}); set `{}` in the [alsa] config",
+                    stream(direction).to_lowercase(),
+                    candidates.join(", "),
+                    setting
+                )
+            })
+    }
+
+    fn mixer_control(&self) -> Result<&str> {
+        if let Some(control) = self.resolved_control.get() {
+            return Ok(control);
+        }
+
+        let output = self
+            .run_amixer(&["scontrols"])
+            .context("Failed to list mixer controls")?;
+        let control = Self::find_control(
+            &Self::parse_controls(&output),
+            self.control.as_deref(),
+            self.direction,
+        )?;
+        Ok(self.resolved_control.get_or_init(|| control))
+    }
+
+    /// ID of the single device this backend addresses
+    fn device_id(&self) -> &str {
+        self.card.as_deref().unwrap_or(DEFAULT_DEVICE)
+    }
+
+    /// Reject endpoints other than the configured card
+    fn check_endpoint(&self, endpoint: &Endpoint) -> Result<()> {
+        match endpoint {
+            Endpoint::Id(id) if id != self.device_id() => anyhow::bail!(
+                "The alsa backend only controls {}; set `card` in the [alsa] config to \
+                 select another device",
+                self.device_label()
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn device_label(&self) -> String {
+        match &self.card {
+            Some(card) => format!("card {}", card),
+            None => format!("'{}' device", DEFAULT_DEVICE),
+        }
+    }
+
+    /// Label and `direction` stream values of one `amixer sget` line, which
+    /// lists each stream of a control with both after the other, e.g.
+    /// `Front Left: Playback 87 [100%] [on] Capture 39 [62%] [on]`
+    fn stream_values(line: &str, direction: Direction) -> Option<(&str, &str)> {
+        let (label, rest) = line.trim().split_once(": ")?;
+        let word = stream(direction);
+        let start = if rest.starts_with(word) {
+            word.len()
+        } else {
+            rest.find(&format!(" {} ", word))? + word.len() + 1
+        };
+        let values = &rest[start..];
+        let end = [" Playback ", " Capture "]
+            .iter()
+            .filter_map(|other| values.find(other))
+            .min()
+            .unwrap_or(values.len());
+        Some((label, values[..end].trim()))
+    }
+
+    /// Extract the level (0.0 - 1.0) and gain of every channel of the
+    /// `direction` stream from `amixer sget` output, e.g.
+    /// `Front Left: Capture 39 [62%] [12.00dB] [on]`
+    fn parse_channels(output: &str, direction: Direction) -> Result<Vec<ChannelVolume>> {
+        let limits = Self::parse_limits(output, direction);
+
+        let mut channels = Vec::new();
+        for line in output.lines() {
+            let Some((name, values)) = Self::stream_values(line, direction) else {
+                continue;
+            };
+            if name == "Limits" {
+                continue;
+            }
+
+            let raw = values
+                .split_whitespace()
+                .next()
+                .and_then(|raw| raw.parse::<i64>().ok());
+            let volume = match (limits, raw) {
+                (Some((min, max)), Some(raw)) if max > min => {
+                    Some(((raw - min) as f32 / (max - min) as f32).clamp(0.0, 1.0))
+                }
+                _ => values
+                    .split('[')
+                    .find_map(|part| part.split_once("%]"))
+                    .and_then(|(percent, _)| percent.parse::<f32>().ok())
+                    .map(|percent| (percent / 100.0).clamp(0.0, 1.0)),
+            };
+            let Some(volume) = volume else {
+                continue;
+            };
+            let db = values
+                .split('[')
+                .find_map(|part| part.split_once("dB]"))
+                .and_then(|(db, _)| db.parse::<f32>().ok());
+
+            channels.push(ChannelVolume {
+                name: name.to_string(),
+                volume,
+                db,
+            });
+        }
+
+        if channels.is_empty() {
+            anyhow::bail!(
+                "Failed to parse {} level from amixer output",
+                stream(direction).to_lowercase()
+            );
+        }
+        Ok(channels)
+    }
+
+    /// Extract the raw value range of the `direction` stream from the
+    /// `Limits:` line of `amixer sget` output
+    fn parse_limits(output: &str, direction: Direction) -> Option<(i64, i64)> {
+        output.lines().find_map(|line| {
+            let (label, rest) = Self::stream_values(line, direction)?;
+            if label != "Limits" {
+                return None;
+            }
+            let mut numbers = rest
+                .split(|c: char| !c.is_ascii_digit() && c != '-')
+                .filter_map(|token| token.parse::<i64>().ok());
+            Some((numbers.next()?, numbers.next()?))
+        })
+    }
+
+    /// Scalar size of one raw step of the control, no finer than the whole
+    /// percent `set_volume` writes
+    fn parse_step(output: &str, direction: Direction) -> f32 {
+        match Self::parse_limits(output, direction) {
+            Some((min, max)) if max > min => (1.0 / (max - min) as f32).max(0.01),
+            _ => 0.01,
+        }
+    }
+
+    /// Extract the level (0.0 - 1.0) of the loudest channel from `amixer
+    /// sget` output, the master volume as PulseAudio and PipeWire report it
+    fn parse_volume(output: &str, direction: Direction) -> Result<f32> {
+        Ok(Self::parse_channels(output, direction)?
+            .iter()
+            .map(|channel| channel.volume)
+            .fold(0.0, f32::max))
+    }
+
+    /// Extract the gain of the loudest channel from `amixer sget` output,
+    /// present only for controls with dB information
+    fn parse_db(output: &str, direction: Direction) -> Result<f32> {
+        Self::parse_channels(output, direction)?
+            .iter()
+            .filter_map(|channel| channel.db)
+            .reduce(f32::max)
+            .with_context(|| format!("{} control has no dB information", stream(direction)))
+    }
+
+    /// Mixer element names the volume of a simple control may have, tried in
+    /// order (e.g. "Mic Capture Volume" for "Mic", "Capture Volume" for
+    /// "Capture")
+    fn volume_element_names(control: &str, direction: Direction) -> [String; 2] {
+        [
+            format!("{} {} Volume", control, stream(direction)),
+            format!("{} Volume", control),
+        ]
+    }
+
+    /// Extract the gain range from `amixer cget` output of a volume element,
+    /// which carries its raw limits and a dB scale
+    fn parse_db_range(output: &str) -> Result<VolumeRange> {
+        let tokens = || output.split([',', ' ', '\n']).map(str::trim);
+        let raw =
+            |key: &str| tokens().find_map(|token| token.strip_prefix(key)?.parse::<i64>().ok());
+        let db = |key: &str| {
+            tokens().find_map(|token| {
+                token
+                    .strip_prefix(key)?
+                    .strip_suffix("dB")?
+                    .parse::<f32>()
+                    .ok()
+            })
+        };
+
+        let (raw_min, raw_max) = raw("min=")
+            .zip(raw("max="))
+            .filter(|(min, max)| max > min)
+            .context("Missing raw limits in amixer output")?;
+        let steps = (raw_max - raw_min) as f32;
+
+        if let Some(min_db) = db("dBscale-min=") {
+            let step = db("step=").context("Missing dB step in amixer output")?;
+            return Ok(VolumeRange {
+                min_db,
+                max_db: min_db + step * steps,
+                step_db: Some(step),
+            });
+        }
+        if let Some(min_db) = db("dBminmax-min=") {
+            let max_db = db("max=").context("Missing dB maximum in amixer output")?;
+            return Ok(VolumeRange {
+                min_db,
+                max_db,
+                step_db: Some((max_db - min_db) / steps),
+            });
+        }
+
+        anyhow::bail!("Volume element has no supported dB scale")
+    }
+
+    /// Extract the switch state of the `direction` stream from `amixer sget`
+    /// output; a switch that is `[off]` means the device is muted
+    fn parse_mute(output: &str, direction: Direction) -> Result<bool> {
+        output
+            .lines()
+            .filter_map(|line| Self::stream_values(line, direction))
+            .find_map(|(_, values)| {
+                if values.contains("[off]") {
+                    Some(true)
+                } else if values.contains("[on]") {
+                    Some(false)
+                } else {
+                    None
+                }
+            })
+            .with_context(|| format!("{} control has no switch", stream(direction)))
+    }
+
+    fn read_control(&self, endpoint: &Endpoint) -> Result<String> {
+        self.check_endpoint(endpoint)?;
+        let control = self.mixer_control()?;
+        self.run_amixer(&["sget", control])
+            .with_context(|| format!("Failed to read mixer control '{}'", control))
+    }
+}
+
+impl VolumeBackend for AudioController {
+    fn name(&self) -> &'static str {
+        "alsa"
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        Self::parse_volume(&self.read_control(endpoint)?, self.direction)
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, target_volume: f32) -> Result<()> {
+        check_volume_range(target_volume)?;
+        self.check_endpoint(endpoint)?;
+
+        let level = format!("{:.0}%", target_volume * 100.0);
+        self.write(&[
+            "-q",
+            "sset",
+            self.mixer_control()?,
+            self.sset_stream(),
+            &level,
+        ])
+        .context("Failed to set volume level")?;
+
+        Ok(())
+    }
+
+    fn volume_step(&self, endpoint: &Endpoint) -> Result<Option<f32>> {
+        Ok(Some(Self::parse_step(
+            &self.read_control(endpoint)?,
+            self.direction,
+        )))
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        Self::parse_db(&self.read_control(endpoint)?, self.direction)
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        self.volume_range(endpoint)?.check(db)?;
+
+        // `--` keeps negative gains from being read as options
+        let level = format!("{:.2}dB", db);
+        self.write(&[
+            "-q",
+            "--",
+            "sset",
+            self.mixer_control()?,
+            self.sset_stream(),
+            &level,
+        ])
+        .context("Failed to set volume level")?;
+
+        Ok(())
+    }
+
+    fn volume_range(&self, endpoint: &Endpoint) -> Result<VolumeRange> {
+        self.check_endpoint(endpoint)?;
+        let control = self.mixer_control()?;
+
+        let mut last_error = None;
+        for element in Self::volume_element_names(control, self.direction) {
+            match self.run_amixer(&["cget", &format!("name={}", element)]) {
+                Ok(output) => return Self::parse_db_range(&output),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .context("No volume element")?
+            .context(format!("Failed to read the dB range of '{}'", control)))
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        Self::parse_channels(&self.read_control(endpoint)?, self.direction)
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        let channels = self.get_channel_volumes(endpoint)?.len();
+        check_channel_volumes(volumes, channels)?;
+
+        // amixer takes comma-separated values, one per channel
+        let levels: Vec<String> = volumes
+            .iter()
+            .map(|volume| format!("{:.0}%", volume * 100.0))
+            .collect();
+        self.write(&[
+            "-q",
+            "sset",
+            self.mixer_control()?,
+            self.sset_stream(),
+            &levels.join(","),
+        ])
+        .context("Failed to set channel volumes")?;
+
+        Ok(())
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        Self::parse_mute(&self.read_control(endpoint)?, self.direction)
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        self.check_endpoint(endpoint)?;
+        let switch = match (self.direction, muted) {
+            (Direction::Capture, true) => "nocap",
+            (Direction::Capture, false) => "cap",
+            (Direction::Render, true) => "mute",
+            (Direction::Render, false) => "unmute",
+        };
+        self.write(&["-q", "sset", self.mixer_control()?, switch])
+            .context("Failed to set mute state")?;
+
+        Ok(())
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        // The backend only addresses the configured card
+        let control = self.read_control(&Endpoint::Default)?;
+        Ok(vec![DeviceInfo {
+            id: self.device_id().to_string(),
+            name: format!("ALSA {} ({})", self.device_label(), self.mixer_control()?),
+            state: DeviceState::Active,
+            volume: Self::parse_volume(&control, self.direction).ok(),
+            muted: Self::parse_mute(&control, self.direction).ok(),
+            default_for: Role::ALL.to_vec(),
+        }])
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        anyhow::bail!("The alsa backend has no per-application streams; they need a sound server")
+    }
+
+    fn set_stream_volume(&self, _id: &str, _volume: f32) -> Result<()> {
+        anyhow::bail!("The alsa backend has no per-application streams; they need a sound server")
+    }
+
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        let control = self.mixer_control()?.to_string();
+        let own_writes = self.own_writes.clone();
+        watch_tool(
+            "amixer",
+            "alsa-utils",
+            &self.amixer_args(&["events"]),
+            events,
+            move |line| {
+                (Self::parse_event(line) == Some(control.as_str())).then(|| {
+                    own_writes.tag(VolumeEvent::VolumeChanged {
+                        device: None,
+                        origin: Origin::External,
+                    })
+                })
+            },
+        )
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mute: true,
+            device_list: false,
+            streams: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGET_OUTPUT: &str = "Simple mixer control 'Capture',0
+  Capabilities: cvolume cswitch
+  Capture channels: Front Left - Front Right
+  Limits: Capture 0 - 65536
+  Front Left: Capture 62259 [95%] [on]
+  Front Right: Capture 62259 [95%] [on]
+";
+
+    #[test]
+    fn test_parse_volume_from_raw_value() {
+        let volume = AudioController::parse_volume(SGET_OUTPUT, Direction::Capture).unwrap();
+        assert!((volume - 0.95).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_parse_volume_from_percent() {
+        let output = "Simple mixer control 'Capture',0
+  Capture channels: Mono
+  Mono: Capture [42%] [on]
+";
+        let volume = AudioController::parse_volume(output, Direction::Capture).unwrap();
+        assert!((volume - 0.42).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_parse_db() {
+        let output = "Simple mixer control 'Capture',0
+  Limits: Capture 0 - 63
+  Front Left: Capture 39 [62%] [12.00dB] [on]
+  Front Right: Capture 39 [62%] [12.00dB] [on]
+";
+        assert_eq!(
+            AudioController::parse_db(output, Direction::Capture).unwrap(),
+            12.0
+        );
+        assert!(AudioController::parse_db(SGET_OUTPUT, Direction::Capture).is_err());
+    }
+
+    #[test]
+    fn test_parse_step() {
+        let coarse = "Simple mixer control 'Capture',0
+  Limits: Capture 0 - 31
+  Mono: Capture 29 [94%] [on]
+";
+        let step = AudioController::parse_step(coarse, Direction::Capture);
+        assert!((step - 1.0 / 31.0).abs() < f32::EPSILON);
+        // Finer controls are still written in whole percent
+        assert_eq!(
+            AudioController::parse_step(SGET_OUTPUT, Direction::Capture),
+            0.01
+        );
+    }
+
+    #[test]
+    fn test_parse_channels() {
+        let output = "Simple mixer control 'Capture',0
+  Limits: Capture 0 - 63
+  Front Left: Capture 63 [100%] [30.00dB] [on]
+  Front Right: Capture 39 [62%] [12.00dB] [on]
+";
+        let channels = AudioController::parse_channels(output, Direction::Capture).unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "Front Left");
+        assert_eq!(channels[0].volume, 1.0);
+        assert_eq!(channels[1].db, Some(12.0));
+
+        // The master volume is the loudest channel, whichever comes first
+        let swapped = output
+            .replace(
+                "Left: Capture 63 [100%] [30.00dB]",
+                "Left: Capture 39 [62%] [12.00dB]",
+            )
+            .replace(
+                "Right: Capture 39 [62%] [12.00dB]",
+                "Right: Capture 63 [100%] [30.00dB]",
+            );
+        for output in [output, swapped.as_str()] {
+            assert_eq!(
+                AudioController::parse_volume(output, Direction::Capture).unwrap(),
+                1.0
+            );
+            assert_eq!(
+                AudioController::parse_db(output, Direction::Capture).unwrap(),
+                30.0
+            );
+        }
+
+        let channels = AudioController::parse_channels(SGET_OUTPUT, Direction::Capture).unwrap();
+        assert_eq!(channels[1].name, "Front Right");
+        assert_eq!(channels[1].db, None);
+    }
+
+    #[test]
+    fn test_parse_db_range() {
+        let scale = "numid=24,iface=MIXER,name='Capture Volume'
+  ; type=INTEGER,access=rw---R--,values=2,min=0,max=63,step=0
+  : values=39,39
+  | dBscale-min=-17.25dB,step=0.75dB,mute=0
+";
+        let range = AudioController::parse_db_range(scale).unwrap();
+        assert_eq!(range.min_db, -17.25);
+        assert_eq!(range.max_db, 30.0);
+        assert_eq!(range.step_db, Some(0.75));
+
+        let minmax = "numid=3,iface=MIXER,name='Mic Capture Volume'
+  ; type=INTEGER,access=rw---R--,values=1,min=0,max=100,step=0
+  : values=50
+  | dBminmax-min=-50.00dB,max=0.00dB
+";
+        let range = AudioController::parse_db_range(minmax).unwrap();
+        assert_eq!((range.min_db, range.max_db), (-50.0, 0.0));
+        assert_eq!(range.step_db, Some(0.5));
+
+        let no_tlv = "numid=3,iface=MIXER,name='Mic Capture Volume'
+  ; type=INTEGER,access=rw------,values=1,min=0,max=100,step=0
+  : values=50
+";
+        assert!(AudioController::parse_db_range(no_tlv).is_err());
+    }
+
+    #[test]
+    fn test_parse_mute() {
+        assert!(!AudioController::parse_mute(SGET_OUTPUT, Direction::Capture).unwrap());
+        assert!(
+            AudioController::parse_mute(&SGET_OUTPUT.replace("[on]", "[off]"), Direction::Capture)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_find_capture_control() {
+        let output = "Simple mixer control 'Master',0
+Simple mixer control 'Mic',0
+Simple mixer control 'Mic Boost',0
+Simple mixer control 'Capture',0
+";
+        let controls = AudioController::parse_controls(output);
+        assert_eq!(controls, ["Master", "Mic", "Mic Boost", "Capture"]);
+
+        assert_eq!(
+            AudioController::find_control(&controls, None, Direction::Capture).unwrap(),
+            "Capture"
+        );
+        assert_eq!(
+            AudioController::find_control(&controls[..2], None, Direction::Capture).unwrap(),
+            "Mic"
+        );
+        assert_eq!(
+            AudioController::find_control(&controls, Some("Mic Boost"), Direction::Capture)
+                .unwrap(),
+            "Mic Boost"
+        );
+        assert!(
+            AudioController::find_control(&controls, Some("Line"), Direction::Capture).is_err()
+        );
+        assert!(AudioController::find_control(&controls[..1], None, Direction::Capture).is_err());
+
+        assert_eq!(
+            AudioController::find_control(&controls, None, Direction::Render).unwrap(),
+            "Master"
+        );
+        assert!(AudioController::find_control(&controls[1..], None, Direction::Render).is_err());
+    }
+
+    #[test]
+    fn test_parse_event() {
+        assert_eq!(
+            AudioController::parse_event("event value: 'Capture',0"),
+            Some("Capture")
+        );
+        assert_eq!(
+            AudioController::parse_event("event value: 'Mic Boost',0\n"),
+            Some("Mic Boost")
+        );
+        assert_eq!(AudioController::parse_event("Ready to listen..."), None);
+        assert_eq!(
+            AudioController::parse_event("event info: 'Capture',0"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_playback() {
+        // A control with both streams, such as "Mic" on some codecs
+        let output = "Simple mixer control 'Master',0
+  Capabilities: pvolume pswitch cvolume cswitch
+  Limits: Playback 0 - 87 Capture 0 - 63
+  Mono: Playback 87 [100%] [0.00dB] [off] Capture 39 [62%] [12.00dB] [on]
+";
+        assert_eq!(
+            AudioController::parse_volume(output, Direction::Render).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            AudioController::parse_db(output, Direction::Render).unwrap(),
+            0.0
+        );
+        assert!(AudioController::parse_mute(output, Direction::Render).unwrap());
+        assert!(!AudioController::parse_mute(output, Direction::Capture).unwrap());
+        let capture = AudioController::parse_channels(output, Direction::Capture).unwrap();
+        assert!((capture[0].volume - 39.0 / 63.0).abs() < 0.001);
+        assert_eq!(capture[0].db, Some(12.0));
+    }
+
+    #[test]
+    fn test_check_endpoint() {
+        let settings = AlsaConfig {
+            card: Some("1".to_string()),
+            control: None,
+            playback_control: None,
+        };
+        let backend = AudioController::new(&settings, Direction::Capture);
+        assert!(backend.check_endpoint(&Endpoint::Default).is_ok());
+        assert!(
+            backend
+                .check_endpoint(&Endpoint::Id("1".to_string()))
+                .is_ok()
+        );
+        assert!(
+            backend
+                .check_endpoint(&Endpoint::Id("0".to_string()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_volume_invalid_output() {
+        assert!(AudioController::parse_volume("", Direction::Capture).is_err());
+    }
+}
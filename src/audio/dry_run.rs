@@ -0,0 +1,376 @@
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
+use anyhow::Result;
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, Endpoint, StreamInfo, VolumeBackend, VolumeEvent,
+    VolumeRange, Watch, check_channel_volumes, check_volume_range,
+};
+use crate::volume::VolumeLevel;
+
+/// Backend for `--dry-run`: reads go to another backend, and every write is
+/// reported instead of made
+///
+/// Later reads return what the writes would have left, so a run that checks
+/// its own work sees it done.
+pub struct DryRunBackend {
+    inner: Box<dyn VolumeBackend>,
+    /// `--quiet`: the writes are not reported either
+    quiet: bool,
+    written: Mutex<Written>,
+}
+
+/// Values the writes of a dry run would have left
+#[derive(Debug, Default)]
+struct Written {
+    devices: Vec<(Endpoint, DeviceWrites)>,
+    /// Stream volumes by stream ID
+    streams: Vec<(String, f32)>,
+}
+
+/// Values written to one device; `None` where it was not written
+#[derive(Debug, Default)]
+struct DeviceWrites {
+    /// Volume, read back in the unit it was written in
+    level: Option<VolumeLevel>,
+    channels: Option<Vec<f32>>,
+    muted: Option<bool>,
+}
+
+impl DryRunBackend {
+    pub fn new(inner: Box<dyn VolumeBackend>, quiet: bool) -> Self {
+        Self {
+            inner,
+            quiet,
+            written: Mutex::default(),
+        }
+    }
+
+    /// Device behind `endpoint`, when the backend lists it
+    fn device(&self, endpoint: &Endpoint) -> Option<DeviceInfo> {
+        self.inner.list_devices().ok().and_then(|devices| {
+            devices.into_iter().find(|device| match endpoint {
+                Endpoint::Default => device.is_default(),
+                Endpoint::Id(id) => &device.id == id,
+            })
+        })
+    }
+
+    /// Friendly name and ID of the device behind `endpoint`, when the backend
+    /// lists it
+    fn device_name(&self, endpoint: &Endpoint) -> String {
+        match self.device(endpoint) {
+            Some(device) => format!("{} ({})", device.name, device.id),
+            None => endpoint.to_string(),
+        }
+    }
+
+    /// Endpoint the writes to `endpoint` are kept under, the same for the
+    /// default device and its ID
+    fn key(&self, endpoint: &Endpoint) -> Endpoint {
+        match (endpoint, self.device(endpoint)) {
+            (Endpoint::Default, Some(device)) => Endpoint::Id(device.id),
+            _ => endpoint.clone(),
+        }
+    }
+
+    /// `read` from the values written to `endpoint`, `None` when they do not
+    /// have it
+    fn written<R>(
+        &self,
+        endpoint: &Endpoint,
+        read: impl FnOnce(&DeviceWrites) -> Option<R>,
+    ) -> Option<R> {
+        let key = self.key(endpoint);
+        let written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        written
+            .devices
+            .iter()
+            .find(|(endpoint, _)| *endpoint == key)
+            .and_then(|(_, writes)| read(writes))
+    }
+
+    /// Note a write to `endpoint`
+    fn write(&self, endpoint: &Endpoint, write: impl FnOnce(&mut DeviceWrites)) {
+        let key = self.key(endpoint);
+        let mut written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        let index = match written
+            .devices
+            .iter()
+            .position(|(endpoint, _)| *endpoint == key)
+        {
+            Some(index) => index,
+            None => {
+                written.devices.push((key, DeviceWrites::default()));
+                written.devices.len() - 1
+            }
+        };
+        write(&mut written.devices[index].1);
+    }
+
+    /// Report a write of `what` on `target`, from the current value when it
+    /// could be read
+    fn report(&self, target: &str, what: &str, from: Option<String>, to: String) {
+        if self.quiet {
+            return;
+        }
+        match from {
+            Some(from) => println!("Would set {} {} from {} to {}", target, what, from, to),
+            None => println!("Would set {} {} to {}", target, what, to),
+        }
+    }
+}
+
+impl VolumeBackend for DryRunBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        match self.written(endpoint, |writes| writes.level) {
+            Some(VolumeLevel::Scalar(volume)) => Ok(volume),
+            _ => self.inner.get_volume(endpoint),
+        }
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+        let from = self.get_volume(endpoint).ok();
+        self.report(
+            &self.device_name(endpoint),
+            "volume",
+            from.map(|volume| VolumeLevel::Scalar(volume).to_string()),
+            VolumeLevel::Scalar(volume).to_string(),
+        );
+
+        // The master volume keeps the balance of the channels
+        let channels = self.get_channel_volumes(endpoint).ok().map(|channels| {
+            let scale = from.filter(|&from| from > 0.0).map(|from| volume / from);
+            channels
+                .iter()
+                .map(|channel| scale.map_or(volume, |scale| (channel.volume * scale).min(1.0)))
+                .collect()
+        });
+        self.write(endpoint, |writes| {
+            writes.level = Some(VolumeLevel::Scalar(volume));
+            writes.channels = channels;
+        });
+        Ok(())
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        match self.written(endpoint, |writes| writes.muted) {
+            Some(muted) => Ok(muted),
+            None => self.inner.get_mute(endpoint),
+        }
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        match self.written(endpoint, |writes| writes.level) {
+            Some(VolumeLevel::Db(db)) => Ok(db),
+            _ => self.inner.get_volume_db(endpoint),
+        }
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        self.inner.volume_range(endpoint)?.check(db)?;
+        let from = self.get_volume_db(endpoint).ok();
+        self.report(
+            &self.device_name(endpoint),
+            "volume",
+            from.map(|db| VolumeLevel::Db(db).to_string()),
+            VolumeLevel::Db(db).to_string(),
+        );
+        self.write(endpoint, |writes| {
+            writes.level = Some(VolumeLevel::Db(db));
+            writes.channels = None;
+        });
+        Ok(())
+    }
+
+    fn volume_range(&self, endpoint: &Endpoint) -> Result<VolumeRange> {
+        self.inner.volume_range(endpoint)
+    }
+
+    fn volume_step(&self, endpoint: &Endpoint) -> Result<Option<f32>> {
+        self.inner.volume_step(endpoint)
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        let channels = self.inner.get_channel_volumes(endpoint)?;
+        match self.written(endpoint, |writes| writes.channels.clone()) {
+            Some(volumes) => Ok(channels
+                .into_iter()
+                .zip(volumes)
+                .map(|(channel, volume)| ChannelVolume {
+                    volume,
+                    db: None,
+                    ..channel
+                })
+                .collect()),
+            None => Ok(channels),
+        }
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        let channels = self.get_channel_volumes(endpoint)?;
+        check_channel_volumes(volumes, channels.len())?;
+        let levels = |volumes: &mut dyn Iterator<Item = f32>| {
+            volumes
+                .map(|volume| VolumeLevel::Scalar(volume).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        self.report(
+            &self.device_name(endpoint),
+            "channels",
+            Some(levels(&mut channels.iter().map(|channel| channel.volume))),
+            levels(&mut volumes.iter().copied()),
+        );
+        self.write(endpoint, |writes| {
+            let master = volumes.iter().copied().fold(0.0, f32::max);
+            writes.level = Some(VolumeLevel::Scalar(master));
+            writes.channels = Some(volumes.to_vec());
+        });
+        Ok(())
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        let state = |muted: bool| if muted { "muted" } else { "unmuted" }.to_string();
+        let from = self.get_mute(endpoint).ok();
+        self.report(
+            &self.device_name(endpoint),
+            "mute state",
+            from.map(state),
+            state(muted),
+        );
+        self.write(endpoint, |writes| writes.muted = Some(muted));
+        Ok(())
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let mut devices = self.inner.list_devices()?;
+        let written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        for device in &mut devices {
+            let endpoint = Endpoint::Id(device.id.clone());
+            let Some((_, writes)) = written.devices.iter().find(|(key, _)| *key == endpoint) else {
+                continue;
+            };
+            if let Some(VolumeLevel::Scalar(volume)) = writes.level {
+                device.volume = Some(volume);
+            }
+            if let Some(muted) = writes.muted {
+                device.muted = Some(muted);
+            }
+        }
+        Ok(devices)
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        let mut streams = self.inner.list_streams()?;
+        let written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        for stream in &mut streams {
+            if let Some((_, volume)) = written.streams.iter().find(|(id, _)| *id == stream.id) {
+                stream.volume = *volume;
+            }
+        }
+        Ok(streams)
+    }
+
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+        let stream = self
+            .list_streams()?
+            .into_iter()
+            .find(|stream| stream.id == id);
+        let target = match &stream {
+            Some(stream) => format!("{} stream ({})", stream.application, id),
+            None => format!("stream {}", id),
+        };
+        self.report(
+            &target,
+            "volume",
+            stream.map(|stream| VolumeLevel::Scalar(stream.volume).to_string()),
+            VolumeLevel::Scalar(volume).to_string(),
+        );
+
+        let mut written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        written.streams.retain(|(written, _)| written != id);
+        written.streams.push((id.to_string(), volume));
+        Ok(())
+    }
+
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        self.inner.watch(events)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::MockBackend;
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let mock = MockBackend::from_spec("Mic=50,channels=50/50")
+            .unwrap()
+            .with_stream("Firefox", 0.3);
+        let backend = DryRunBackend::new(Box::new(mock.clone()), true);
+        let endpoint = Endpoint::Default;
+
+        backend.set_volume(&endpoint, 0.8).unwrap();
+        backend.set_level(&endpoint, VolumeLevel::Db(-6.0)).unwrap();
+        backend.set_channel_volumes(&endpoint, &[0.8, 0.7]).unwrap();
+        backend.set_mute(&endpoint, true).unwrap();
+        backend.set_stream_volume("stream-0", 0.9).unwrap();
+        assert_eq!(mock.devices()[0].volume, 0.5);
+        assert!(!mock.devices()[0].muted);
+        assert_eq!(mock.streams()[0].volume, 0.3);
+
+        // Writes the backend would reject fail the same way
+        assert!(backend.set_volume(&endpoint, 1.5).is_err());
+        assert!(backend.set_level(&endpoint, VolumeLevel::Db(6.0)).is_err());
+        assert!(backend.set_channel_volumes(&endpoint, &[0.8]).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_reads_back_writes() {
+        let mock = MockBackend::from_spec("Mic=50,channels=50/25; USB=40")
+            .unwrap()
+            .with_stream("Firefox", 0.3);
+        let backend = DryRunBackend::new(Box::new(mock), true);
+        let endpoint = Endpoint::Default;
+        let channels = |backend: &DryRunBackend| -> Vec<f32> {
+            backend
+                .get_channel_volumes(&endpoint)
+                .unwrap()
+                .iter()
+                .map(|channel| channel.volume)
+                .collect()
+        };
+
+        // The default device and its ID are the same device
+        let id = Endpoint::Id("mock-0".to_string());
+        backend.set_volume(&id, 0.8).unwrap();
+        assert_eq!(backend.get_volume(&endpoint).unwrap(), 0.8);
+        assert_eq!(channels(&backend), [0.8, 0.4]);
+        assert_eq!(backend.list_devices().unwrap()[0].volume, Some(0.8));
+        let usb = Endpoint::Id("mock-1".to_string());
+        assert_eq!(backend.get_volume(&usb).unwrap(), 0.4);
+
+        backend.set_channel_volumes(&endpoint, &[0.6, 0.6]).unwrap();
+        assert_eq!(backend.get_volume(&endpoint).unwrap(), 0.6);
+        backend.set_volume_db(&endpoint, -12.0).unwrap();
+        assert_eq!(backend.get_volume_db(&endpoint).unwrap(), -12.0);
+
+        backend.set_mute(&endpoint, true).unwrap();
+        assert!(backend.get_mute(&endpoint).unwrap());
+        backend.set_stream_volume("stream-0", 0.9).unwrap();
+        assert_eq!(backend.list_streams().unwrap()[0].volume, 0.9);
+    }
+}
@@ -0,0 +1,750 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, mpsc::Sender},
+};
+
+use anyhow::{Context, Result};
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, DeviceState, Endpoint, Origin, Role, StreamInfo,
+    VolumeBackend, VolumeEvent, VolumeRange, Watch, check_channel_volumes, check_volume_range,
+};
+
+/// Gain range of every simulated device; the scalar maps linearly onto it
+const MOCK_RANGE: VolumeRange = VolumeRange {
+    min_db: -60.0,
+    max_db: 0.0,
+    step_db: Some(0.5),
+};
+
+/// Backend operation that can be scripted to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    GetVolume,
+    SetVolume,
+    GetMute,
+    SetMute,
+    ListDevices,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::GetVolume => "get_volume",
+            Operation::SetVolume => "set_volume",
+            Operation::GetMute => "get_mute",
+            Operation::SetMute => "set_mute",
+            Operation::ListDevices => "list_devices",
+        })
+    }
+}
+
+impl FromStr for Operation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "get_volume" => Operation::GetVolume,
+            "set_volume" => Operation::SetVolume,
+            "get_mute" => Operation::GetMute,
+            "set_mute" => Operation::SetMute,
+            "list_devices" => Operation::ListDevices,
+            _ => anyhow::bail!("Unknown mock operation '{}'", s),
+        })
+    }
+}
+
+/// Simulated capture device
+///
+/// Like a WASAPI endpoint, the master volume is the loudest channel and
+/// setting it scales every channel, keeping their balance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockDevice {
+    pub id: String,
+    pub name: String,
+    pub volume: f32,
+    /// Per-channel volumes; stereo at `volume` unless set otherwise
+    pub channels: Vec<f32>,
+    pub muted: bool,
+    pub state: DeviceState,
+    /// Roles this device is the default for; the first device added starts
+    /// out as the default for all of them
+    pub default_for: Vec<Role>,
+    /// Number of steps of a coarse volume control, which rounds every level
+    /// written to the nearest one; continuous when `None`
+    pub steps: Option<u32>,
+}
+
+impl MockDevice {
+    /// Rounding of levels written to the volume control
+    fn rounding(&self) -> impl Fn(f32) -> f32 + use<> {
+        let steps = self.steps;
+        move |volume| match steps {
+            Some(steps) => (volume * steps as f32).round() / steps as f32,
+            None => volume,
+        }
+    }
+
+    fn set_master(&mut self, volume: f32) {
+        let round = self.rounding();
+        let volume = round(volume);
+        let scale = if self.volume > 0.0 {
+            volume / self.volume
+        } else {
+            0.0
+        };
+        for channel in &mut self.channels {
+            *channel = if scale > 0.0 {
+                round((*channel * scale).min(1.0))
+            } else {
+                volume
+            };
+        }
+        self.volume = volume;
+    }
+
+    fn set_channels(&mut self, volumes: &[f32]) {
+        self.channels = volumes.iter().copied().map(self.rounding()).collect();
+        self.volume = self.channels.iter().copied().fold(0.0, f32::max);
+    }
+}
+
+/// Simulated application capture stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockStream {
+    pub id: String,
+    pub application: String,
+    pub volume: f32,
+}
+
+fn scalar_to_db(volume: f32) -> f32 {
+    MOCK_RANGE.min_db + volume * (MOCK_RANGE.max_db - MOCK_RANGE.min_db)
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    devices: Vec<MockDevice>,
+    streams: Vec<MockStream>,
+    failures: Vec<Operation>,
+    watchers: Vec<Sender<VolumeEvent>>,
+}
+
+impl MockState {
+    /// Tell every watcher still listening about `event`
+    fn notify(&mut self, event: VolumeEvent) {
+        self.watchers
+            .retain(|watcher| watcher.send(event.clone()).is_ok());
+    }
+}
+
+/// In-memory volume backend with scriptable devices and failures
+///
+/// dB and stream operations share the failures of their device counterparts
+/// (listing streams those of `list_devices`). Clones share
+/// state, so a test can keep a handle to inspect what a command
+/// did to the devices, or change them behind its back. Every successful
+/// write is reported to watchers, as our own unless made through an
+/// `external` handle, and so are devices added or changed through the
+/// builder methods, as if plugged in.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    /// Origin reported for the writes of this handle
+    external: bool,
+}
+
+impl MockBackend {
+    /// Backend with no devices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a device; the first device added is the default one
+    pub fn with_device(self, name: &str, volume: f32, muted: bool) -> Self {
+        {
+            let mut state = self.lock();
+            let id = format!("mock-{}", state.devices.len());
+            let default_for = if state.devices.is_empty() {
+                Role::ALL.to_vec()
+            } else {
+                Vec::new()
+            };
+            state.devices.push(MockDevice {
+                id: id.clone(),
+                name: name.to_string(),
+                volume,
+                channels: vec![volume; 2],
+                muted,
+                state: DeviceState::Active,
+                default_for,
+                steps: None,
+            });
+            state.notify(VolumeEvent::DeviceAdded { device: Some(id) });
+        }
+        self
+    }
+
+    /// Change the state of the most recently added device
+    pub fn with_state(self, device_state: DeviceState) -> Self {
+        {
+            let mut state = self.lock();
+            if let Some(device) = state.devices.last_mut() {
+                device.state = device_state;
+                let device = Some(device.id.clone());
+                state.notify(match device_state {
+                    DeviceState::Active => VolumeEvent::DeviceAdded { device },
+                    _ => VolumeEvent::DeviceRemoved { device },
+                });
+            }
+        }
+        self
+    }
+
+    /// Set the channel volumes of the most recently added device
+    pub fn with_channels(self, volumes: &[f32]) -> Self {
+        if let Some(device) = self.lock().devices.last_mut() {
+            device.set_channels(volumes);
+        }
+        self
+    }
+
+    /// Give the most recently added device a coarse volume control with
+    /// `steps` steps, and round its current volume to them
+    pub fn with_steps(self, steps: u32) -> Self {
+        if let Some(device) = self.lock().devices.last_mut() {
+            device.steps = Some(steps);
+            let channels = device.channels.clone();
+            device.set_channels(&channels);
+        }
+        self
+    }
+
+    /// Make the most recently added device the default for `role`
+    pub fn with_default_for(self, role: Role) -> Self {
+        {
+            let mut state = self.lock();
+            for device in &mut state.devices {
+                device.default_for.retain(|&r| r != role);
+            }
+            if let Some(device) = state.devices.last_mut() {
+                device.default_for.push(role);
+            }
+            state.notify(VolumeEvent::DefaultChanged);
+        }
+        self
+    }
+
+    /// Add a capture stream opened by `application`
+    pub fn with_stream(self, application: &str, volume: f32) -> Self {
+        {
+            let mut state = self.lock();
+            let id = format!("stream-{}", state.streams.len());
+            state.streams.push(MockStream {
+                id,
+                application: application.to_string(),
+                volume,
+            });
+        }
+        self
+    }
+
+    /// Make every call of `operation` fail
+    pub fn with_failure(self, operation: Operation) -> Self {
+        self.lock().failures.push(operation);
+        self
+    }
+
+    /// Build a backend from a `;`-separated script
+    ///
+    /// Each entry is either a device, `<name>=<percent>[,<flag>...]` with the
+    /// flags `muted`, `disabled`, `unplugged`, `channels=<percent>/...`
+    /// (per-channel volumes), `steps=<count>` (a coarse volume control) and
+    /// `default=<role>`, an application stream,
+    /// `stream=<application>=<percent>`, or an injected failure,
+    /// `fail=<operation>`. An empty script yields a single
+    /// unmuted "Mock Microphone" at 50%.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let mut backend = Self::new();
+
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, value) = entry
+                .split_once('=')
+                .with_context(|| format!("Invalid mock entry '{}': expected name=value", entry))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "fail" {
+                backend = backend.with_failure(value.parse()?);
+                continue;
+            }
+            if key == "stream" {
+                let (application, percent) = value
+                    .rsplit_once('=')
+                    .with_context(|| format!("Invalid mock stream '{}'", value))?;
+                let percent: f32 = percent
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid volume for mock stream '{}'", application))?;
+                backend = backend.with_stream(application.trim(), percent / 100.0);
+                continue;
+            }
+
+            let mut flags = value.split(',').map(str::trim);
+            let percent: f32 = flags
+                .next()
+                .unwrap_or_default()
+                .parse()
+                .with_context(|| format!("Invalid volume for mock device '{}'", key))?;
+            let mut muted = false;
+            let mut device_state = DeviceState::Active;
+            let mut channels = None;
+            let mut roles = Vec::new();
+            let mut steps = None;
+            for flag in flags {
+                match flag {
+                    "muted" => muted = true,
+                    "disabled" => device_state = DeviceState::Disabled,
+                    "unplugged" => device_state = DeviceState::Unplugged,
+                    _ if flag.starts_with("channels=") => {
+                        let levels = flag["channels=".len()..]
+                            .split('/')
+                            .map(|percent| percent.trim().parse::<f32>().map(|p| p / 100.0))
+                            .collect::<Result<Vec<_>, _>>()
+                            .with_context(|| {
+                                format!("Invalid channel volumes for mock device '{}'", key)
+                            })?;
+                        channels = Some(levels);
+                    }
+                    _ if flag.starts_with("steps=") => {
+                        let count = flag["steps=".len()..]
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|&count| count > 0)
+                            .with_context(|| {
+                                format!("Invalid step count for mock device '{}'", key)
+                            })?;
+                        steps = Some(count);
+                    }
+                    _ if flag.starts_with("default=") => {
+                        let role = Role::ALL
+                            .into_iter()
+                            .find(|role| role.to_string() == flag["default=".len()..])
+                            .with_context(|| {
+                                format!("Unknown role in '{}' for mock device '{}'", flag, key)
+                            })?;
+                        roles.push(role);
+                    }
+                    _ => anyhow::bail!("Unknown flag '{}' for mock device '{}'", flag, key),
+                }
+            }
+            backend = backend
+                .with_device(key, percent / 100.0, muted)
+                .with_state(device_state);
+            if let Some(channels) = channels {
+                backend = backend.with_channels(&channels);
+            }
+            if let Some(steps) = steps {
+                backend = backend.with_steps(steps);
+            }
+            for role in roles {
+                backend = backend.with_default_for(role);
+            }
+        }
+
+        if backend.lock().devices.is_empty() {
+            backend = backend.with_device("Mock Microphone", 0.5, false);
+        }
+
+        Ok(backend)
+    }
+
+    /// Handle on the same devices whose writes are reported as made by
+    /// another application
+    #[cfg(test)]
+    pub fn external(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+            external: true,
+        }
+    }
+
+    fn origin(&self) -> Origin {
+        if self.external {
+            Origin::External
+        } else {
+            Origin::Own
+        }
+    }
+
+    /// Snapshot of every simulated device
+    #[cfg(test)]
+    pub fn devices(&self) -> Vec<MockDevice> {
+        self.lock().devices.clone()
+    }
+
+    /// Snapshot of every simulated stream
+    #[cfg(test)]
+    pub fn streams(&self) -> Vec<MockStream> {
+        self.lock().streams.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        // A panic while holding the lock cannot leave the state inconsistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with_device_at<T>(
+        &self,
+        endpoint: &Endpoint,
+        operation: Operation,
+        f: impl FnOnce(&mut MockDevice) -> T,
+    ) -> Result<T> {
+        let mut state = self.lock();
+        if state.failures.contains(&operation) {
+            anyhow::bail!("Injected {} failure", operation);
+        }
+        let device = match endpoint {
+            Endpoint::Default => state
+                .devices
+                .iter_mut()
+                .find(|device| device.default_for.contains(&Role::Console))
+                .context("No default microphone")?,
+            Endpoint::Id(id) => state
+                .devices
+                .iter_mut()
+                .find(|device| &device.id == id)
+                .with_context(|| format!("No device with ID '{}'", id))?,
+        };
+        let result = f(device);
+        if matches!(operation, Operation::SetVolume | Operation::SetMute) {
+            let device = Some(device.id.clone());
+            state.notify(VolumeEvent::VolumeChanged {
+                device,
+                origin: self.origin(),
+            });
+        }
+        Ok(result)
+    }
+}
+
+impl VolumeBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        self.with_device_at(endpoint, Operation::GetVolume, |device| device.volume)
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+        self.with_device_at(endpoint, Operation::SetVolume, |device| {
+            device.set_master(volume)
+        })
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        Ok(scalar_to_db(self.get_volume(endpoint)?))
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        MOCK_RANGE.check(db)?;
+        self.set_volume(
+            endpoint,
+            (db - MOCK_RANGE.min_db) / (MOCK_RANGE.max_db - MOCK_RANGE.min_db),
+        )
+    }
+
+    fn volume_range(&self, endpoint: &Endpoint) -> Result<VolumeRange> {
+        self.with_device_at(endpoint, Operation::GetVolume, |_| MOCK_RANGE)
+    }
+
+    fn volume_step(&self, endpoint: &Endpoint) -> Result<Option<f32>> {
+        self.with_device_at(endpoint, Operation::GetVolume, |device| {
+            device.steps.map(|steps| 1.0 / steps as f32)
+        })
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        self.with_device_at(endpoint, Operation::GetVolume, |device| {
+            device
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(index, &volume)| ChannelVolume {
+                    name: format!("channel {}", index + 1),
+                    volume,
+                    db: Some(scalar_to_db(volume)),
+                })
+                .collect()
+        })
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        self.with_device_at(endpoint, Operation::SetVolume, |device| {
+            check_channel_volumes(volumes, device.channels.len())?;
+            device.set_channels(volumes);
+            Ok(())
+        })?
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        self.with_device_at(endpoint, Operation::GetMute, |device| device.muted)
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        self.with_device_at(endpoint, Operation::SetMute, |device| device.muted = muted)
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let state = self.lock();
+        if state.failures.contains(&Operation::ListDevices) {
+            anyhow::bail!("Injected {} failure", Operation::ListDevices);
+        }
+        Ok(state
+            .devices
+            .iter()
+            .map(|device| DeviceInfo {
+                id: device.id.clone(),
+                name: device.name.clone(),
+                state: device.state,
+                volume: Some(device.volume),
+                muted: Some(device.muted),
+                default_for: device.default_for.clone(),
+            })
+            .collect())
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        let state = self.lock();
+        if state.failures.contains(&Operation::ListDevices) {
+            anyhow::bail!("Injected {} failure", Operation::ListDevices);
+        }
+        Ok(state
+            .streams
+            .iter()
+            .map(|stream| StreamInfo {
+                id: stream.id.clone(),
+                application: stream.application.clone(),
+                volume: stream.volume,
+                muted: Some(false),
+            })
+            .collect())
+    }
+
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+        let mut state = self.lock();
+        if state.failures.contains(&Operation::SetVolume) {
+            anyhow::bail!("Injected {} failure", Operation::SetVolume);
+        }
+        let stream = state
+            .streams
+            .iter_mut()
+            .find(|stream| stream.id == id)
+            .with_context(|| format!("No stream with ID '{}'", id))?;
+        stream.volume = volume;
+        state.notify(VolumeEvent::VolumeChanged {
+            device: None,
+            origin: self.origin(),
+        });
+        Ok(())
+    }
+
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        self.lock().watchers.push(events);
+        Ok(Watch::new(()))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mute: true,
+            device_list: true,
+            streams: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_spec() {
+        let backend = MockBackend::from_spec("").unwrap();
+        let devices = backend.list_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert!(devices[0].is_default());
+        assert_eq!(backend.get_volume(&Endpoint::Default).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_spec_devices_and_failures() {
+        let backend =
+            MockBackend::from_spec("Built-in=40,muted; USB Mic=80,unplugged; fail=set_volume")
+                .unwrap();
+        let devices = backend.devices();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].name, "USB Mic");
+        assert_eq!(devices[1].state, DeviceState::Unplugged);
+        assert_eq!(backend.get_volume(&Endpoint::Default).unwrap(), 0.4);
+        assert!(backend.get_mute(&Endpoint::Default).unwrap());
+        assert!(backend.set_volume(&Endpoint::Default, 0.9).is_err());
+        assert!(backend.set_mute(&Endpoint::Default, false).is_ok());
+    }
+
+    #[test]
+    fn test_spec_streams() {
+        let backend = MockBackend::from_spec("Mic=50; stream=Firefox=40; stream=Zoom=100").unwrap();
+        let streams = backend.list_streams().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].application, "Firefox");
+        assert_eq!(streams[0].volume, 0.4);
+
+        backend.set_stream_volume(&streams[1].id, 0.9).unwrap();
+        assert_eq!(backend.streams()[1].volume, 0.9);
+        assert!(backend.set_stream_volume("missing", 0.9).is_err());
+        assert!(MockBackend::from_spec("stream=Firefox").is_err());
+    }
+
+    #[test]
+    fn test_watch() {
+        let backend = MockBackend::from_spec("Mic=50; stream=Firefox=40").unwrap();
+        let (sender, events) = std::sync::mpsc::channel();
+        let _watch = backend.watch(sender).unwrap();
+
+        backend.clone().set_volume(&Endpoint::Default, 0.7).unwrap();
+        backend
+            .external()
+            .set_stream_volume("stream-0", 0.9)
+            .unwrap();
+        backend.get_volume(&Endpoint::Default).unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                VolumeEvent::VolumeChanged {
+                    device: Some("mock-0".to_string()),
+                    origin: Origin::Own,
+                },
+                VolumeEvent::VolumeChanged {
+                    device: None,
+                    origin: Origin::External,
+                },
+            ]
+        );
+
+        // Failed writes change nothing
+        let backend = backend.with_failure(Operation::SetMute);
+        assert!(backend.set_mute(&Endpoint::Default, true).is_err());
+        assert!(events.try_recv().is_err());
+
+        let _backend = backend
+            .with_device("USB Mic", 0.9, false)
+            .with_default_for(Role::Console)
+            .with_state(DeviceState::Unplugged);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                VolumeEvent::DeviceAdded {
+                    device: Some("mock-1".to_string())
+                },
+                VolumeEvent::DefaultChanged,
+                VolumeEvent::DeviceRemoved {
+                    device: Some("mock-1".to_string())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_spec() {
+        assert!(MockBackend::from_spec("Mic").is_err());
+        assert!(MockBackend::from_spec("Mic=loud").is_err());
+        assert!(MockBackend::from_spec("Mic=50,deaf").is_err());
+        assert!(MockBackend::from_spec("fail=explode").is_err());
+    }
+
+    #[test]
+    fn test_clones_share_state() {
+        let backend = MockBackend::new().with_device("Mic", 0.1, false);
+        let handle = backend.clone();
+        backend.set_volume(&Endpoint::Default, 0.7).unwrap();
+        assert_eq!(handle.devices()[0].volume, 0.7);
+    }
+
+    #[test]
+    fn test_endpoint_by_id() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, false)
+            .with_device("USB Mic", 0.8, false);
+        let usb = Endpoint::Id("mock-1".to_string());
+
+        backend.set_volume(&usb, 0.6).unwrap();
+        assert_eq!(backend.get_volume(&usb).unwrap(), 0.6);
+        assert_eq!(backend.get_volume(&Endpoint::Default).unwrap(), 0.4);
+        assert!(
+            backend
+                .get_volume(&Endpoint::Id("mock-7".to_string()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_volume_db() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        assert_eq!(backend.get_volume_db(&Endpoint::Default).unwrap(), -30.0);
+
+        backend.set_volume_db(&Endpoint::Default, -15.0).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.75);
+        assert!(backend.set_volume_db(&Endpoint::Default, 6.0).is_err());
+        assert_eq!(
+            backend.volume_range(&Endpoint::Default).unwrap(),
+            MOCK_RANGE
+        );
+    }
+
+    #[test]
+    fn test_channel_volumes() {
+        let backend = MockBackend::from_spec("Mic=80,channels=80/40").unwrap();
+        let endpoint = Endpoint::Default;
+        let volumes = |backend: &MockBackend| -> Vec<f32> {
+            backend
+                .get_channel_volumes(&endpoint)
+                .unwrap()
+                .iter()
+                .map(|channel| channel.volume)
+                .collect()
+        };
+        assert_eq!(volumes(&backend), [0.8, 0.4]);
+
+        // The master volume keeps the balance
+        backend.set_volume(&endpoint, 0.4).unwrap();
+        assert_eq!(volumes(&backend), [0.4, 0.2]);
+
+        backend.set_channel_volumes(&endpoint, &[0.5, 0.6]).unwrap();
+        assert_eq!(backend.get_volume(&endpoint).unwrap(), 0.6);
+        assert!(backend.set_channel_volumes(&endpoint, &[0.5]).is_err());
+        assert!(backend.set_channel_volumes(&endpoint, &[0.5, 1.5]).is_err());
+        assert!(MockBackend::from_spec("Mic=80,channels=80/x").is_err());
+    }
+
+    #[test]
+    fn test_default_roles() {
+        let backend =
+            MockBackend::from_spec("Built-in=40; Headset=60,default=communications").unwrap();
+        let devices = backend.list_devices().unwrap();
+        assert_eq!(devices[0].default_for, [Role::Console, Role::Multimedia]);
+        assert_eq!(devices[1].default_for, [Role::Communications]);
+
+        let backend = backend.with_default_for(Role::Console);
+        assert_eq!(backend.get_volume(&Endpoint::Default).unwrap(), 0.6);
+        assert!(MockBackend::from_spec("Mic=50,default=phone").is_err());
+    }
+
+    #[test]
+    fn test_no_devices() {
+        let backend = MockBackend::new();
+        assert!(backend.get_volume(&Endpoint::Default).is_err());
+        assert!(backend.list_devices().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, DeviceState, Direction, Endpoint, Origin, OwnWrites,
+    Role, SOFTWARE_VOLUME_RANGE, StreamInfo, VolumeBackend, VolumeEvent, VolumeRange, Watch,
+    check_channel_volumes, check_volume_range, db_to_software_volume, run_tool,
+    software_volume_to_db, spawn_monitor,
+};
+
+/// Media class of the nodes controlled in `direction`
+fn media_class(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Capture => "Audio/Source",
+        Direction::Render => "Audio/Sink",
+    }
+}
+
+/// Media class of the application streams on nodes of `direction`
+fn stream_media_class(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Capture => "Stream/Input/Audio",
+        Direction::Render => "Stream/Output/Audio",
+    }
+}
+
+/// Key of the default node in the `default` metadata object
+fn default_key(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Capture => "default.audio.source",
+        Direction::Render => "default.audio.sink",
+    }
+}
+
+/// `Audio/Source` (or, for output, `Audio/Sink`) node as reported by
+/// `pw-dump`
+#[derive(Debug, Clone, PartialEq)]
+struct SourceNode {
+    id: u64,
+    name: String,
+    description: String,
+    /// Linear per-channel gains from the node's `Props` param
+    channel_volumes: Vec<f32>,
+    /// Channel positions ("FL", "FR") matching `channel_volumes`
+    channel_map: Vec<String>,
+    mute: bool,
+}
+
+impl SourceNode {
+    /// Volume scalar (0.0 - 1.0) of the loudest channel
+    fn volume(&self) -> Option<f32> {
+        self.channel_volumes
+            .iter()
+            .copied()
+            .reduce(f32::max)
+            .map(|linear| linear.cbrt().min(1.0))
+    }
+
+    fn channels(&self) -> Vec<ChannelVolume> {
+        self.channel_volumes
+            .iter()
+            .enumerate()
+            .map(|(index, linear)| {
+                let volume = linear.cbrt().min(1.0);
+                ChannelVolume {
+                    name: self
+                        .channel_map
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(|| format!("channel {}", index + 1)),
+                    volume,
+                    db: Some(software_volume_to_db(volume)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Sources (or sinks) and the default's name extracted from one `pw-dump`
+/// snapshot
+#[derive(Debug, Default)]
+struct Graph {
+    sources: Vec<SourceNode>,
+    default_source: Option<String>,
+    /// Application stream nodes, described by their application name
+    streams: Vec<SourceNode>,
+}
+
+/// Turns `pw-dump --monitor` updates into events, keeping track of the
+/// device nodes seen so far to tell new ones from changed ones
+struct GraphMonitor {
+    direction: Direction,
+    /// Names of the present device nodes, by node ID
+    devices: HashMap<u64, String>,
+}
+
+impl GraphMonitor {
+    fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Events for the objects of one update; devices are named, streams are
+    /// not
+    fn update(&mut self, objects: &[Value]) -> Vec<VolumeEvent> {
+        let mut events = Vec::new();
+
+        for object in objects {
+            // Removed objects are reported without their info
+            if object["info"].is_null() && !object["type"].is_string() {
+                if let Some(name) = object["id"]
+                    .as_u64()
+                    .and_then(|id| self.devices.remove(&id))
+                {
+                    events.push(VolumeEvent::DeviceRemoved { device: Some(name) });
+                }
+                continue;
+            }
+
+            match object["type"].as_str() {
+                Some("PipeWire:Interface:Node") => {
+                    let params_changed = object["info"]["change-mask"]
+                        .as_array()
+                        .is_none_or(|mask| mask.iter().any(|change| change == "params"));
+                    if let Some(source) =
+                        AudioController::parse_node(object, media_class(self.direction))
+                    {
+                        let device = Some(source.name.clone());
+                        if self.devices.insert(source.id, source.name).is_none() {
+                            events.push(VolumeEvent::DeviceAdded { device });
+                        } else if params_changed {
+                            events.push(VolumeEvent::VolumeChanged {
+                                device,
+                                origin: Origin::External,
+                            });
+                        }
+                    } else if params_changed
+                        && AudioController::parse_node(object, stream_media_class(self.direction))
+                            .is_some()
+                    {
+                        events.push(VolumeEvent::VolumeChanged {
+                            device: None,
+                            origin: Origin::External,
+                        });
+                    }
+                }
+                Some("PipeWire:Interface:Metadata")
+                    if object["props"]["metadata.name"] == "default" =>
+                {
+                    events.push(VolumeEvent::DefaultChanged);
+                }
+                _ => {}
+            }
+        }
+
+        events
+    }
+}
+
+/// Microphone or output volume control through the native PipeWire tools
+/// (`pw-dump`, `pw-cli`)
+///
+/// Unlike the PulseAudio layer this sees the node's own `channelVolumes`. They
+/// are linear gains; like `wpctl` and pavucontrol, the volume scalar is their
+/// cube root. The monitor does not say who changed a node, so every change
+/// to a node within `OWN_WRITE_WINDOW` of one of our writes to it is reported
+/// as ours, whoever made it.
+pub struct AudioController {
+    direction: Direction,
+    own_writes: OwnWrites,
+}
+
+impl AudioController {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            own_writes: OwnWrites::default(),
+        }
+    }
+
+    /// Whether a PipeWire daemon answers
+    pub fn is_available() -> bool {
+        run_tool("pw-cli", "pipewire-bin", &["info", "0"]).is_ok()
+    }
+
+    fn parse_graph(dump: &str, direction: Direction) -> Result<Graph> {
+        let objects: Vec<Value> =
+            serde_json::from_str(dump).context("Failed to parse pw-dump output")?;
+        let mut graph = Graph::default();
+
+        for object in &objects {
+            match object["type"].as_str() {
+                Some("PipeWire:Interface:Node") => {
+                    if let Some(source) = Self::parse_node(object, media_class(direction)) {
+                        graph.sources.push(source);
+                    } else if let Some(mut stream) =
+                        Self::parse_node(object, stream_media_class(direction))
+                    {
+                        if let Some(application) =
+                            object["info"]["props"]["application.name"].as_str()
+                        {
+                            stream.description = application.to_string();
+                        }
+                        graph.streams.push(stream);
+                    }
+                }
+                Some("PipeWire:Interface:Metadata")
+                    if object["props"]["metadata.name"] == "default" =>
+                {
+                    graph.default_source = Self::parse_default_source(object, direction);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn parse_node(object: &Value, media_class: &str) -> Option<SourceNode> {
+        let info = &object["info"];
+        let props = &info["props"];
+        if props["media.class"] != media_class {
+            return None;
+        }
+
+        let name = props["node.name"].as_str()?.to_string();
+        let description = props["node.description"]
+            .as_str()
+            .or(props["node.nick"].as_str())
+            .unwrap_or(&name)
+            .to_string();
+
+        // A node may list several Props objects; the one with channel volumes
+        // carries the software volume
+        let volume_props = info["params"]["Props"]
+            .as_array()
+            .and_then(|params| params.iter().find(|p| p["channelVolumes"].is_array()));
+        let channel_volumes = volume_props
+            .and_then(|p| p["channelVolumes"].as_array())
+            .map(|volumes| {
+                volumes
+                    .iter()
+                    .filter_map(|v| v.as_f64().map(|v| v as f32))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let channel_map = volume_props
+            .and_then(|p| p["channelMap"].as_array())
+            .map(|positions| {
+                positions
+                    .iter()
+                    .filter_map(|p| p.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mute = volume_props
+            .and_then(|p| p["mute"].as_bool())
+            .unwrap_or(false);
+
+        Some(SourceNode {
+            id: object["id"].as_u64()?,
+            name,
+            description,
+            channel_volumes,
+            channel_map,
+            mute,
+        })
+    }
+
+    fn parse_default_source(metadata: &Value, direction: Direction) -> Option<String> {
+        metadata["metadata"]
+            .as_array()?
+            .iter()
+            .find(|entry| entry["key"] == default_key(direction))
+            .and_then(|entry| entry["value"]["name"].as_str())
+            .map(str::to_string)
+    }
+
+    fn read_graph(&self) -> Result<Graph> {
+        let dump = run_tool("pw-dump", "pipewire-bin", &[]).context("Failed to dump graph")?;
+        Self::parse_graph(&dump, self.direction)
+    }
+
+    /// Source (or sink) node for `endpoint`; device IDs are node names
+    fn source(&self, endpoint: &Endpoint) -> Result<SourceNode> {
+        let graph = self.read_graph()?;
+        let name = match endpoint {
+            Endpoint::Default => graph
+                .default_source
+                .with_context(|| format!("PipeWire has no default {} device", self.direction))?,
+            Endpoint::Id(name) => name.clone(),
+        };
+        graph
+            .sources
+            .into_iter()
+            .find(|source| source.name == name)
+            .with_context(|| format!("Node '{}' not found", name))
+    }
+
+    fn set_props(&self, node: &SourceNode, props: Value) -> Result<()> {
+        self.own_writes.record(Some(&node.name));
+        run_tool(
+            "pw-cli",
+            "pipewire-bin",
+            &[
+                "set-param",
+                &node.id.to_string(),
+                "Props",
+                &props.to_string(),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl VolumeBackend for AudioController {
+    fn name(&self) -> &'static str {
+        "pipewire"
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        let source = self.source(endpoint)?;
+        source
+            .volume()
+            .with_context(|| format!("Node '{}' has no channel volumes", source.name))
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, target_volume: f32) -> Result<()> {
+        check_volume_range(target_volume)?;
+
+        let source = self.source(endpoint)?;
+        if source.channel_volumes.is_empty() {
+            anyhow::bail!("Node '{}' has no channel volumes", source.name);
+        }
+
+        let linear = target_volume.powi(3);
+        let volumes = vec![linear; source.channel_volumes.len()];
+        self.set_props(&source, serde_json::json!({ "channelVolumes": volumes }))
+            .context("Failed to set volume level")
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        Ok(software_volume_to_db(self.get_volume(endpoint)?))
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        SOFTWARE_VOLUME_RANGE.check(db)?;
+        self.set_volume(endpoint, db_to_software_volume(db))
+    }
+
+    fn volume_range(&self, _endpoint: &Endpoint) -> Result<VolumeRange> {
+        Ok(SOFTWARE_VOLUME_RANGE)
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        Ok(self.source(endpoint)?.channels())
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        let source = self.source(endpoint)?;
+        check_channel_volumes(volumes, source.channel_volumes.len())?;
+
+        let linear: Vec<f32> = volumes.iter().map(|volume| volume.powi(3)).collect();
+        self.set_props(&source, serde_json::json!({ "channelVolumes": linear }))
+            .context("Failed to set channel volumes")
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        Ok(self.source(endpoint)?.mute)
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        let source = self.source(endpoint)?;
+        self.set_props(&source, serde_json::json!({ "mute": muted }))
+            .context("Failed to set mute state")
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let graph = self.read_graph()?;
+        Ok(graph
+            .sources
+            .into_iter()
+            .map(|source| DeviceInfo {
+                state: DeviceState::Active,
+                volume: source.volume(),
+                muted: Some(source.mute),
+                default_for: if graph.default_source.as_deref() == Some(source.name.as_str()) {
+                    Role::ALL.to_vec()
+                } else {
+                    Vec::new()
+                },
+                id: source.name,
+                name: source.description,
+            })
+            .collect())
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        let graph = self.read_graph()?;
+        Ok(graph
+            .streams
+            .into_iter()
+            .filter_map(|stream| {
+                Some(StreamInfo {
+                    id: stream.id.to_string(),
+                    volume: stream.volume()?,
+                    muted: Some(stream.mute),
+                    application: stream.description,
+                })
+            })
+            .collect())
+    }
+
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+
+        let stream = self
+            .read_graph()?
+            .streams
+            .into_iter()
+            .find(|stream| stream.id.to_string() == id)
+            .with_context(|| format!("Stream '{}' not found", id))?;
+        if stream.channel_volumes.is_empty() {
+            anyhow::bail!("Stream '{}' has no channel volumes", id);
+        }
+
+        let volumes = vec![volume.powi(3); stream.channel_volumes.len()];
+        self.set_props(&stream, serde_json::json!({ "channelVolumes": volumes }))
+            .context("Failed to set stream volume")
+    }
+
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        let (process, stdout) =
+            spawn_monitor("pw-dump", "pipewire-bin", &["--monitor", "--no-colors"])?;
+        let mut monitor = GraphMonitor::new(self.direction);
+        let own_writes = self.own_writes.clone();
+
+        // Every update is a JSON array of the changed objects; the first is
+        // the full graph, which only tells which devices exist
+        std::thread::spawn(move || {
+            let updates = serde_json::Deserializer::from_reader(std::io::BufReader::new(stdout))
+                .into_iter::<Vec<Value>>();
+            for (index, update) in updates.enumerate() {
+                let Ok(objects) = update else {
+                    break;
+                };
+                let changes = monitor.update(&objects);
+                if index == 0 {
+                    continue;
+                }
+                for event in changes {
+                    if events.send(own_writes.tag(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Watch::new(process))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mute: true,
+            device_list: true,
+            streams: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"[
+  { "id": 0, "type": "PipeWire:Interface:Core", "info": { "props": {} } },
+  {
+    "id": 31,
+    "type": "PipeWire:Interface:Metadata",
+    "props": { "metadata.name": "default" },
+    "metadata": [
+      { "subject": 0, "key": "default.audio.sink", "type": "Spa:String:JSON",
+        "value": { "name": "null-sink" } },
+      { "subject": 0, "key": "default.audio.source", "type": "Spa:String:JSON",
+        "value": { "name": "null-source" } }
+    ]
+  },
+  {
+    "id": 57,
+    "type": "PipeWire:Interface:Node",
+    "info": {
+      "props": {
+        "media.class": "Stream/Input/Audio",
+        "node.name": "Firefox",
+        "application.name": "Firefox"
+      },
+      "params": { "Props": [ { "channelVolumes": [ 0.064 ], "mute": false } ] }
+    }
+  },
+  {
+    "id": 42,
+    "type": "PipeWire:Interface:Node",
+    "info": {
+      "props": { "media.class": "Audio/Sink", "node.name": "null-sink" },
+      "params": { "Props": [ { "channelVolumes": [ 1.0, 1.0 ], "mute": false } ] }
+    }
+  },
+  {
+    "id": 43,
+    "type": "PipeWire:Interface:Node",
+    "info": {
+      "props": {
+        "media.class": "Audio/Source",
+        "node.name": "null-source",
+        "node.description": "Null Source"
+      },
+      "params": {
+        "Props": [
+          { "volume": 1.0, "mute": false, "channelVolumes": [ 0.512, 0.216 ],
+            "channelMap": [ "FL", "FR" ] },
+          { "params": [] }
+        ]
+      }
+    }
+  },
+  {
+    "id": 44,
+    "type": "PipeWire:Interface:Node",
+    "info": {
+      "props": { "media.class": "Audio/Source", "node.name": "usb-mic", "node.nick": "USB" },
+      "params": { "Props": [ { "channelVolumes": [ 1.0 ], "mute": true } ] }
+    }
+  }
+]"#;
+
+    #[test]
+    fn test_parse_graph() {
+        let graph = AudioController::parse_graph(DUMP, Direction::Capture).unwrap();
+        assert_eq!(graph.default_source.as_deref(), Some("null-source"));
+        assert_eq!(graph.sources.len(), 2);
+
+        let source = &graph.sources[0];
+        assert_eq!(source.id, 43);
+        assert_eq!(source.description, "Null Source");
+        assert_eq!(source.channel_volumes, vec![0.512, 0.216]);
+        assert!((source.volume().unwrap() - 0.8).abs() < 0.001);
+        assert!(!source.mute);
+        let channels = source.channels();
+        assert_eq!(channels[1].name, "FR");
+        assert!((channels[1].volume - 0.6).abs() < 0.001);
+
+        assert_eq!(graph.streams.len(), 1);
+        assert_eq!(graph.streams[0].id, 57);
+        assert_eq!(graph.streams[0].description, "Firefox");
+        assert!((graph.streams[0].volume().unwrap() - 0.4).abs() < 0.001);
+
+        let usb = &graph.sources[1];
+        assert_eq!(usb.description, "USB");
+        assert!(usb.mute);
+        assert_eq!(usb.channels()[0].name, "channel 1");
+    }
+
+    #[test]
+    fn test_parse_graph_sinks() {
+        let graph = AudioController::parse_graph(DUMP, Direction::Render).unwrap();
+        assert_eq!(graph.default_source.as_deref(), Some("null-sink"));
+        assert_eq!(graph.sources.len(), 1);
+        assert_eq!(graph.sources[0].id, 42);
+        assert_eq!(graph.sources[0].volume(), Some(1.0));
+        assert!(graph.streams.is_empty());
+    }
+
+    #[test]
+    fn test_graph_monitor() {
+        let mut monitor = GraphMonitor::new(Direction::Capture);
+        let objects: Vec<Value> = serde_json::from_str(DUMP).unwrap();
+        let changed = |name: Option<&str>| VolumeEvent::VolumeChanged {
+            device: name.map(str::to_string),
+            origin: Origin::External,
+        };
+        assert_eq!(
+            monitor.update(&objects),
+            [
+                VolumeEvent::DefaultChanged,
+                changed(None),
+                VolumeEvent::DeviceAdded {
+                    device: Some("null-source".to_string())
+                },
+                VolumeEvent::DeviceAdded {
+                    device: Some("usb-mic".to_string())
+                },
+            ]
+        );
+        assert_eq!(
+            monitor.update(&objects[3..]),
+            [changed(Some("null-source")), changed(Some("usb-mic"))]
+        );
+
+        // A node going idle changes its state, not its params
+        let update = serde_json::json!([
+            {
+                "id": 43,
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                    "change-mask": [ "state" ],
+                    "props": { "media.class": "Audio/Source", "node.name": "null-source" }
+                }
+            },
+            { "id": 44, "info": null },
+            { "id": 57, "info": null }
+        ]);
+        assert_eq!(
+            monitor.update(update.as_array().unwrap()),
+            [VolumeEvent::DeviceRemoved {
+                device: Some("usb-mic".to_string())
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_graph_without_default() {
+        let graph = AudioController::parse_graph("[]", Direction::Capture).unwrap();
+        assert!(graph.sources.is_empty());
+        assert!(graph.default_source.is_none());
+
+        assert!(AudioController::parse_graph("not json", Direction::Capture).is_err());
+    }
+
+    // Requires a PipeWire daemon with a null source as default, e.g. with
+    // `pipewire` and `wireplumber` running:
+    // `pw-cli create-node adapter '{ factory.name=support.null-audio-sink
+    // node.name=null-source media.class=Audio/Source audio.position=[FL FR]
+    // object.linger=true }'` and `wpctl set-default <id>`
+    #[test]
+    #[ignore]
+    fn test_null_source_roundtrip() {
+        let backend = AudioController::new(Direction::Capture);
+        let endpoint = Endpoint::Default;
+        backend.set_volume(&endpoint, 0.8).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!((backend.get_volume(&endpoint).unwrap() - 0.8).abs() < 0.001);
+
+        backend.set_mute(&endpoint, true).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(backend.get_mute(&endpoint).unwrap());
+        backend.set_mute(&endpoint, false).unwrap();
+
+        let null_source = Endpoint::Id("null-source".to_string());
+        assert!((backend.get_volume(&null_source).unwrap() - 0.8).abs() < 0.001);
+    }
+}
@@ -0,0 +1,592 @@
+use anyhow::{Context, Result};
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, DeviceState, Direction, Endpoint, Origin, OwnWrites,
+    Role, SOFTWARE_VOLUME_RANGE, StreamInfo, VolumeBackend, VolumeEvent, VolumeRange, Watch,
+    check_channel_volumes, check_volume_range, db_to_software_volume, run_tool,
+    software_volume_to_db, watch_tool,
+};
+use std::sync::mpsc::Sender;
+
+/// Raw volume corresponding to 100% (`PA_VOLUME_NORM`)
+const VOLUME_NORM: f32 = 65536.0;
+
+/// Client name our pactl connections show on the server, e.g. in
+/// `pactl list clients`; it names them for the user only, as change events
+/// do not carry the client
+const CLIENT_NAME: &str = "mic-volume-control";
+
+/// Volume control through the PulseAudio `pactl` utility, of sources
+/// (microphones) or sinks (output devices)
+///
+/// Also works with PipeWire through its PulseAudio compatibility layer.
+/// Change events say neither which client made a change nor to which device
+/// beyond its index, so every change within `OWN_WRITE_WINDOW` of one of our
+/// writes is reported as ours, whoever made it.
+pub struct AudioController {
+    direction: Direction,
+    own_writes: OwnWrites,
+}
+
+impl AudioController {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            own_writes: OwnWrites::default(),
+        }
+    }
+
+    fn run_pactl(args: &[&str]) -> Result<String> {
+        let mut full_args = vec!["--client-name", CLIENT_NAME];
+        full_args.extend_from_slice(args);
+        run_tool("pactl", "pulseaudio-utils", &full_args)
+    }
+
+    /// Run a pactl command that changes a volume or mute state
+    fn write(&self, args: &[&str]) -> Result<String> {
+        // The server only reports indices, so the write covers any device
+        self.own_writes.record(None);
+        Self::run_pactl(args)
+    }
+
+    /// Object kind in pactl commands: `source` or `sink`
+    fn kind(&self) -> &'static str {
+        match self.direction {
+            Direction::Capture => "source",
+            Direction::Render => "sink",
+        }
+    }
+
+    /// Kind of the application streams on this kind of device: `source-output`
+    /// (recording) or `sink-input` (playback)
+    fn stream_kind(&self) -> &'static str {
+        match self.direction {
+            Direction::Capture => "source-output",
+            Direction::Render => "sink-input",
+        }
+    }
+
+    /// pactl subcommand acting on this kind of device, e.g.
+    /// `get-source-volume`
+    fn command(&self, verb: &str, property: &str) -> String {
+        format!("{}-{}-{}", verb, self.kind(), property)
+    }
+
+    /// Source or sink name for `endpoint`; device IDs are their names, and
+    /// the default is resolved by the server at call time
+    fn device<'a>(&self, endpoint: &'a Endpoint) -> &'a str {
+        match (endpoint, self.direction) {
+            (Endpoint::Default, Direction::Capture) => "@DEFAULT_SOURCE@",
+            (Endpoint::Default, Direction::Render) => "@DEFAULT_SINK@",
+            (Endpoint::Id(name), _) => name,
+        }
+    }
+
+    /// Whether a PulseAudio-compatible server answers
+    pub fn is_available() -> bool {
+        Self::run_pactl(&["info"]).is_ok()
+    }
+
+    /// Extract the channel levels from `pactl get-source-volume` output,
+    /// e.g. `front-left: 62259 /  95% / -1.34 dB`
+    fn parse_channels(output: &str) -> Result<Vec<ChannelVolume>> {
+        let line = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Volume:"))
+            .context("Missing volume in pactl output")?;
+
+        let channels: Vec<ChannelVolume> = line
+            .split(',')
+            .filter_map(|channel| {
+                let (name, values) = channel.split_once(':')?;
+                let mut values = values.split('/').map(str::trim);
+                let raw = values.next()?.parse::<u32>().ok()?;
+                let db = values
+                    .nth(1)
+                    .and_then(|db| db.strip_suffix("dB"))
+                    .and_then(|db| db.trim().parse::<f32>().ok());
+                Some(ChannelVolume {
+                    name: name.trim().to_string(),
+                    // Over-amplified sources report more than 100%
+                    volume: (raw as f32 / VOLUME_NORM).min(1.0),
+                    db,
+                })
+            })
+            .collect();
+
+        if channels.is_empty() {
+            anyhow::bail!("Failed to parse channel volumes from pactl output");
+        }
+        Ok(channels)
+    }
+
+    /// Extract the source level (0.0 - 1.0) from `pactl get-source-volume`
+    /// output; the loudest channel wins, matching PulseAudio's own notion of
+    /// the overall volume
+    fn parse_volume(output: &str) -> Result<f32> {
+        Ok(Self::parse_channels(output)?
+            .iter()
+            .map(|channel| channel.volume)
+            .fold(0.0, f32::max))
+    }
+
+    fn parse_mute(output: &str) -> Result<bool> {
+        match output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Mute:"))
+            .map(str::trim)
+        {
+            Some("yes") => Ok(true),
+            Some("no") => Ok(false),
+            _ => anyhow::bail!("Failed to parse mute state from pactl output"),
+        }
+    }
+
+    /// Default source or sink name from `pactl info` output
+    fn parse_default(info: &str, direction: Direction) -> Option<String> {
+        let key = match direction {
+            Direction::Capture => "Default Source:",
+            Direction::Render => "Default Sink:",
+        };
+        info.lines()
+            .find_map(|line| line.trim().strip_prefix(key))
+            .map(|name| name.trim().to_string())
+    }
+
+    /// Devices from `pactl list sources` or `pactl list sinks`, skipping
+    /// sink monitors
+    fn parse_devices(output: &str, default: Option<&str>, direction: Direction) -> Vec<DeviceInfo> {
+        let header = match direction {
+            Direction::Capture => "Source #",
+            Direction::Render => "Sink #",
+        };
+        let mut devices = Vec::new();
+
+        for block in output.split(header).skip(1) {
+            let field = |key: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.trim().strip_prefix(key))
+                    .map(str::trim)
+            };
+
+            let Some(name) = field("Name:") else {
+                continue;
+            };
+            if field("Monitor of Sink:").is_some_and(|sink| sink != "n/a") {
+                continue;
+            }
+
+            devices.push(DeviceInfo {
+                id: name.to_string(),
+                name: field("Description:").unwrap_or(name).to_string(),
+                state: DeviceState::Active,
+                volume: Self::parse_volume(block).ok(),
+                muted: Self::parse_mute(block).ok(),
+                default_for: if default == Some(name) {
+                    Role::ALL.to_vec()
+                } else {
+                    Vec::new()
+                },
+            });
+        }
+
+        devices
+    }
+
+    /// Application streams from `pactl list source-outputs` or `pactl list
+    /// sink-inputs`; streams without a volume are skipped
+    fn parse_streams(output: &str, direction: Direction) -> Vec<StreamInfo> {
+        let header = match direction {
+            Direction::Capture => "Source Output #",
+            Direction::Render => "Sink Input #",
+        };
+        let mut streams = Vec::new();
+
+        for block in output.split(header).skip(1) {
+            let Some(index) = block.lines().next().map(str::trim) else {
+                continue;
+            };
+            let Ok(volume) = Self::parse_volume(block) else {
+                continue;
+            };
+            let property = |key: &str| {
+                block.lines().find_map(|line| {
+                    let value = line.trim().strip_prefix(key)?.trim_start();
+                    Some(value.strip_prefix('=')?.trim().trim_matches('"'))
+                })
+            };
+            let application = property("application.name")
+                .or_else(|| property("application.process.binary"))
+                .map_or_else(|| format!("stream #{}", index), str::to_string);
+
+            streams.push(StreamInfo {
+                id: index.to_string(),
+                application,
+                volume,
+                muted: Self::parse_mute(block).ok(),
+            });
+        }
+
+        streams
+    }
+
+    /// Event from a `pactl subscribe` line, e.g. `Event 'change' on source
+    /// #1`; the server only reports indices, so devices are left unnamed
+    ///
+    /// Server changes are reported as default changes, the default source and
+    /// sink being server properties.
+    fn parse_event(&self, line: &str) -> Option<VolumeEvent> {
+        let (event, object) = line.trim().strip_prefix("Event '")?.split_once("' on ")?;
+        let kind = object.split(" #").next()?;
+        match (event, kind) {
+            ("change", "server") => Some(VolumeEvent::DefaultChanged),
+            ("change", _) if kind == self.kind() || kind == self.stream_kind() => {
+                Some(VolumeEvent::VolumeChanged {
+                    device: None,
+                    origin: Origin::External,
+                })
+            }
+            ("new", _) if kind == self.kind() => Some(VolumeEvent::DeviceAdded { device: None }),
+            ("remove", _) if kind == self.kind() => {
+                Some(VolumeEvent::DeviceRemoved { device: None })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl VolumeBackend for AudioController {
+    fn name(&self) -> &'static str {
+        "pulse"
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        let output = Self::run_pactl(&[&self.command("get", "volume"), self.device(endpoint)])
+            .context("Failed to get volume level")?;
+        Self::parse_volume(&output)
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, target_volume: f32) -> Result<()> {
+        check_volume_range(target_volume)?;
+
+        // A raw value keeps full precision; percentages are rounded by pactl
+        let raw = (target_volume * VOLUME_NORM).round() as u32;
+        self.write(&[
+            &self.command("set", "volume"),
+            self.device(endpoint),
+            &raw.to_string(),
+        ])
+        .context("Failed to set volume level")?;
+
+        Ok(())
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        Ok(software_volume_to_db(self.get_volume(endpoint)?))
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        SOFTWARE_VOLUME_RANGE.check(db)?;
+        self.set_volume(endpoint, db_to_software_volume(db))
+    }
+
+    fn volume_range(&self, _endpoint: &Endpoint) -> Result<VolumeRange> {
+        Ok(SOFTWARE_VOLUME_RANGE)
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        let output = Self::run_pactl(&[&self.command("get", "volume"), self.device(endpoint)])
+            .context("Failed to get volume level")?;
+        Self::parse_channels(&output)
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        let channels = self.get_channel_volumes(endpoint)?.len();
+        check_channel_volumes(volumes, channels)?;
+
+        // pactl takes one volume per channel, in channel map order
+        let raw: Vec<String> = volumes
+            .iter()
+            .map(|volume| ((volume * VOLUME_NORM).round() as u32).to_string())
+            .collect();
+        let command = self.command("set", "volume");
+        let mut args = vec![command.as_str(), self.device(endpoint)];
+        args.extend(raw.iter().map(String::as_str));
+        self.write(&args).context("Failed to set channel volumes")?;
+
+        Ok(())
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        let output = Self::run_pactl(&[&self.command("get", "mute"), self.device(endpoint)])
+            .context("Failed to get mute state")?;
+        Self::parse_mute(&output)
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        let state = if muted { "1" } else { "0" };
+        self.write(&[&self.command("set", "mute"), self.device(endpoint), state])
+            .context("Failed to set mute state")?;
+
+        Ok(())
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let info = Self::run_pactl(&["info"]).context("Failed to query server info")?;
+        let default = Self::parse_default(&info, self.direction);
+
+        let output = Self::run_pactl(&["list", &format!("{}s", self.kind())])
+            .with_context(|| format!("Failed to list {} devices", self.direction))?;
+        Ok(Self::parse_devices(
+            &output,
+            default.as_deref(),
+            self.direction,
+        ))
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        let output = Self::run_pactl(&["list", &format!("{}s", self.stream_kind())])
+            .context("Failed to list application streams")?;
+        Ok(Self::parse_streams(&output, self.direction))
+    }
+
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+
+        let raw = (volume * VOLUME_NORM).round() as u32;
+        self.write(&[
+            &format!("set-{}-volume", self.stream_kind()),
+            id,
+            &raw.to_string(),
+        ])
+        .context("Failed to set stream volume")?;
+
+        Ok(())
+    }
+
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        let controller = Self {
+            direction: self.direction,
+            own_writes: self.own_writes.clone(),
+        };
+        watch_tool(
+            "pactl",
+            "pulseaudio-utils",
+            &["--client-name", CLIENT_NAME, "subscribe"],
+            events,
+            move |line| {
+                controller
+                    .parse_event(line)
+                    .map(|event| controller.own_writes.tag(event))
+            },
+        )
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mute: true,
+            device_list: true,
+            streams: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST_SOURCES: &str = "Source #0
+\tState: SUSPENDED
+\tName: alsa_output.pci-0000_00_1f.3.analog-stereo.monitor
+\tDescription: Monitor of Built-in Audio Analog Stereo
+\tMute: no
+\tVolume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+\t        balance 0.00
+\tBase Volume: 65536 / 100% / 0.00 dB
+\tMonitor of Sink: alsa_output.pci-0000_00_1f.3.analog-stereo
+
+Source #1
+\tState: RUNNING
+\tName: alsa_input.usb-Blue_Yeti-00.analog-stereo
+\tDescription: Yeti Stereo Microphone Analog Stereo
+\tMute: yes
+\tVolume: front-left: 52429 /  80% / -5.81 dB,   front-right: 52429 /  80% / -5.81 dB
+\t        balance 0.00
+\tBase Volume: 65536 / 100% / 0.00 dB
+\tMonitor of Sink: n/a
+
+Source #2
+\tState: IDLE
+\tName: null-source
+\tDescription: Null Input
+\tMonitor of Sink: n/a
+";
+
+    #[test]
+    fn test_parse_streams() {
+        let output = "Source Output #42
+\tDriver: protocol-native.c
+\tSource: 1
+\tMute: no
+\tVolume: mono: 26214 /  40% / -23.88 dB
+\t        balance 0.00
+\tProperties:
+\t\tmedia.name = \"WebRTC\"
+\t\tapplication.name = \"Firefox\"
+\t\tapplication.process.binary = \"firefox\"
+
+Source Output #43
+\tMute: yes
+\tVolume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+\tProperties:
+\t\tapplication.process.binary = \"arecord\"
+
+Source Output #44
+\tVolume: n/a
+\tProperties:
+\t\tapplication.name = \"Peak meter\"
+";
+        let streams = AudioController::parse_streams(output, Direction::Capture);
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].id, "42");
+        assert_eq!(streams[0].application, "Firefox");
+        assert!((streams[0].volume - 0.4).abs() < 0.001);
+        assert_eq!(streams[0].muted, Some(false));
+        assert_eq!(streams[1].application, "arecord");
+        assert_eq!(streams[1].muted, Some(true));
+
+        assert!(AudioController::parse_streams(output, Direction::Render).is_empty());
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let changed = Some(VolumeEvent::VolumeChanged {
+            device: None,
+            origin: Origin::External,
+        });
+        let parse = |line, direction| AudioController::new(direction).parse_event(line);
+        assert_eq!(
+            parse("Event 'change' on source #1", Direction::Capture),
+            changed
+        );
+        assert_eq!(
+            parse("Event 'change' on source-output #42", Direction::Capture),
+            changed
+        );
+        assert_eq!(parse("Event 'change' on sink #0", Direction::Capture), None);
+        assert_eq!(
+            parse("Event 'change' on sink #0", Direction::Render),
+            changed
+        );
+        assert_eq!(
+            parse("Event 'new' on source-output #43", Direction::Capture),
+            None
+        );
+        assert_eq!(
+            parse("Event 'change' on client #7", Direction::Capture),
+            None
+        );
+
+        assert_eq!(
+            parse("Event 'new' on source #3", Direction::Capture),
+            Some(VolumeEvent::DeviceAdded { device: None })
+        );
+        assert_eq!(
+            parse("Event 'remove' on source #3", Direction::Capture),
+            Some(VolumeEvent::DeviceRemoved { device: None })
+        );
+        assert_eq!(parse("Event 'remove' on sink #3", Direction::Capture), None);
+        assert_eq!(
+            parse("Event 'change' on server #4294967295", Direction::Capture),
+            Some(VolumeEvent::DefaultChanged)
+        );
+    }
+
+    #[test]
+    fn test_parse_volume() {
+        let output = "Volume: front-left: 62259 /  95% / -1.34 dB,   front-right: 52429 /  80% / \
+                      -5.81 dB\n        balance -0.16\n";
+        let volume = AudioController::parse_volume(output).unwrap();
+        assert!((volume - 0.95).abs() < 0.001);
+
+        let mono = "Volume: mono: 65536 / 100% / 0.00 dB\n";
+        assert_eq!(AudioController::parse_volume(mono).unwrap(), 1.0);
+
+        let boosted = "Volume: mono: 98304 / 150% / 10.57 dB\n";
+        assert_eq!(AudioController::parse_volume(boosted).unwrap(), 1.0);
+
+        assert!(AudioController::parse_volume("").is_err());
+    }
+
+    #[test]
+    fn test_parse_channels() {
+        let output = "Volume: front-left: 62259 /  95% / -1.34 dB,   front-right: 0 /   0% / \
+                      -inf dB\n        balance -1.00\n";
+        let channels = AudioController::parse_channels(output).unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "front-left");
+        assert!((channels[0].volume - 0.95).abs() < 0.001);
+        assert_eq!(channels[0].db, Some(-1.34));
+        assert_eq!(channels[1].volume, 0.0);
+        assert_eq!(channels[1].db, Some(f32::NEG_INFINITY));
+
+        assert!(AudioController::parse_channels("Volume: balance\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_mute() {
+        assert!(AudioController::parse_mute("Mute: yes\n").unwrap());
+        assert!(!AudioController::parse_mute("Mute: no\n").unwrap());
+        assert!(AudioController::parse_mute("").is_err());
+    }
+
+    #[test]
+    fn test_parse_sources() {
+        let info = "Server Name: pulseaudio\nDefault Sink: null\nDefault Source: null-source\n";
+        let default_source = AudioController::parse_default(info, Direction::Capture);
+        assert_eq!(default_source.as_deref(), Some("null-source"));
+        assert_eq!(
+            AudioController::parse_default(info, Direction::Render).as_deref(),
+            Some("null")
+        );
+
+        let devices = AudioController::parse_devices(
+            LIST_SOURCES,
+            default_source.as_deref(),
+            Direction::Capture,
+        );
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].id, "alsa_input.usb-Blue_Yeti-00.analog-stereo");
+        assert_eq!(devices[0].name, "Yeti Stereo Microphone Analog Stereo");
+        assert!((devices[0].volume.unwrap() - 0.8).abs() < 0.001);
+        assert_eq!(devices[0].muted, Some(true));
+        assert!(!devices[0].is_default());
+        assert!(devices[1].is_default());
+        assert_eq!(devices[1].volume, None);
+    }
+
+    // Requires a running server with a null source as default, e.g.
+    // `pactl load-module module-null-source source_name=null-source` and
+    // `pactl set-default-source null-source`
+    #[test]
+    #[ignore]
+    fn test_null_source_roundtrip() {
+        let backend = AudioController::new(Direction::Capture);
+        let endpoint = Endpoint::Default;
+        backend.set_volume(&endpoint, 0.42).unwrap();
+        assert!((backend.get_volume(&endpoint).unwrap() - 0.42).abs() < 0.001);
+
+        backend.set_mute(&endpoint, true).unwrap();
+        assert!(backend.get_mute(&endpoint).unwrap());
+        backend.set_mute(&endpoint, false).unwrap();
+        assert!(!backend.get_mute(&endpoint).unwrap());
+
+        let devices = backend.list_devices().unwrap();
+        assert!(devices.iter().any(|device| device.is_default()));
+
+        let null_source = Endpoint::Id("null-source".to_string());
+        backend.set_volume(&null_source, 0.3).unwrap();
+        assert!((backend.get_volume(&endpoint).unwrap() - 0.3).abs() < 0.001);
+    }
+}
@@ -0,0 +1,610 @@
+use std::{path::Path, sync::mpsc::Sender};
+
+use anyhow::{Context, Result};
+use windows::{
+    Win32::Devices::FunctionDiscovery::*,
+    Win32::Foundation::{BOOL, CloseHandle, S_OK},
+    Win32::Media::Audio::Endpoints::*,
+    Win32::Media::Audio::*,
+    Win32::System::Com::*,
+    Win32::System::Threading::{
+        OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+        QueryFullProcessImageNameW,
+    },
+    Win32::UI::Shell::PropertiesSystem::PROPERTYKEY,
+    core::{GUID, HSTRING, Interface, PCWSTR, PWSTR, implement},
+};
+
+use super::{
+    Capabilities, ChannelVolume, DeviceInfo, DeviceState, Direction, Endpoint, Origin, Role,
+    StreamInfo, VolumeBackend, VolumeEvent, VolumeRange, Watch, check_channel_volumes,
+    check_volume_range,
+};
+
+/// Event context passed with every write, so notifications tell our own
+/// changes from those of other applications
+const EVENT_CONTEXT: GUID = GUID::from_u128(0x6d1c3f0a_4b7e_4c52_9a1e_2f8d5b0c7e31);
+
+/// Endpoint volume callback forwarding every notification of one device
+#[implement(IAudioEndpointVolumeCallback)]
+struct VolumeCallback {
+    device: String,
+    events: Sender<VolumeEvent>,
+}
+
+impl IAudioEndpointVolumeCallback_Impl for VolumeCallback_Impl {
+    fn OnNotify(&self, data: *mut AUDIO_VOLUME_NOTIFICATION_DATA) -> windows::core::Result<()> {
+        let origin = match unsafe { data.as_ref() } {
+            Some(data) if data.guidEventContext == EVENT_CONTEXT => Origin::Own,
+            _ => Origin::External,
+        };
+        // A closed channel means the watch is being dropped
+        let _ = self.events.send(VolumeEvent::VolumeChanged {
+            device: Some(self.device.clone()),
+            origin,
+        });
+        Ok(())
+    }
+}
+
+/// Callback registered on an endpoint, unregistered on drop
+struct Registration {
+    volume: IAudioEndpointVolume,
+    callback: IAudioEndpointVolumeCallback,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.volume.UnregisterControlChangeNotify(&self.callback);
+        }
+    }
+}
+
+/// Device notification client reporting endpoints that come, go or become
+/// a default, for one data flow
+#[implement(IMMNotificationClient)]
+struct DeviceCallback {
+    flow: EDataFlow,
+    events: Sender<VolumeEvent>,
+}
+
+impl DeviceCallback {
+    fn send(&self, event: VolumeEvent) {
+        // A closed channel means the watch is being dropped
+        let _ = self.events.send(event);
+    }
+}
+
+impl IMMNotificationClient_Impl for DeviceCallback_Impl {
+    fn OnDeviceStateChanged(
+        &self,
+        device_id: &PCWSTR,
+        new_state: DEVICE_STATE,
+    ) -> windows::core::Result<()> {
+        let device = unsafe { device_id.to_string() }.ok();
+        self.send(match new_state {
+            DEVICE_STATE_ACTIVE => VolumeEvent::DeviceAdded { device },
+            _ => VolumeEvent::DeviceRemoved { device },
+        });
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, device_id: &PCWSTR) -> windows::core::Result<()> {
+        let device = unsafe { device_id.to_string() }.ok();
+        self.send(VolumeEvent::DeviceAdded { device });
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, device_id: &PCWSTR) -> windows::core::Result<()> {
+        let device = unsafe { device_id.to_string() }.ok();
+        self.send(VolumeEvent::DeviceRemoved { device });
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        _role: ERole,
+        _device_id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        if flow == self.flow {
+            self.send(VolumeEvent::DefaultChanged);
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(
+        &self,
+        _device_id: &PCWSTR,
+        _key: &PROPERTYKEY,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+/// Device notification client registered on an enumerator, unregistered on
+/// drop
+struct DeviceRegistration {
+    enumerator: IMMDeviceEnumerator,
+    client: IMMNotificationClient,
+}
+
+impl Drop for DeviceRegistration {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .enumerator
+                .UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
+
+/// Microphone or output volume control through the Windows Core Audio
+/// (WASAPI) API
+pub struct AudioController {
+    direction: Direction,
+}
+
+impl AudioController {
+    pub fn new(direction: Direction) -> Self {
+        Self { direction }
+    }
+
+    /// Data flow of the endpoints this controller addresses
+    fn data_flow(&self) -> EDataFlow {
+        match self.direction {
+            Direction::Capture => eCapture,
+            Direction::Render => eRender,
+        }
+    }
+
+    fn get_device_enumerator() -> Result<IMMDeviceEnumerator> {
+        unsafe {
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .context("Failed to create device enumerator")
+        }
+    }
+
+    fn get_default_endpoint(
+        &self,
+        enumerator: &IMMDeviceEnumerator,
+        role: Role,
+    ) -> Result<IMMDevice> {
+        let role = match role {
+            Role::Console => eConsole,
+            Role::Multimedia => eMultimedia,
+            Role::Communications => eCommunications,
+        };
+
+        unsafe {
+            enumerator
+                .GetDefaultAudioEndpoint(self.data_flow(), role)
+                .with_context(|| format!("Failed to get default {} device", self.direction))
+        }
+    }
+
+    fn get_volume_control(device: &IMMDevice) -> Result<IAudioEndpointVolume> {
+        unsafe {
+            device
+                .Activate(CLSCTX_ALL, None)
+                .context("Failed to activate audio endpoint volume")
+        }
+    }
+
+    fn get_device(
+        &self,
+        enumerator: &IMMDeviceEnumerator,
+        endpoint: &Endpoint,
+    ) -> Result<IMMDevice> {
+        match endpoint {
+            Endpoint::Default => self.get_default_endpoint(enumerator, Role::Console),
+            Endpoint::Id(id) => unsafe {
+                enumerator
+                    .GetDevice(&HSTRING::from(id.as_str()))
+                    .with_context(|| format!("Failed to get device '{}'", id))
+            },
+        }
+    }
+
+    fn get_endpoint_volume_control(&self, endpoint: &Endpoint) -> Result<IAudioEndpointVolume> {
+        let enumerator = Self::get_device_enumerator()?;
+        let device = self.get_device(&enumerator, endpoint)?;
+        Self::get_volume_control(&device)
+    }
+
+    fn read_volume_range(volume: &IAudioEndpointVolume) -> Result<VolumeRange> {
+        let (mut min_db, mut max_db, mut step_db) = (0.0, 0.0, 0.0);
+
+        unsafe {
+            volume
+                .GetVolumeRange(&mut min_db, &mut max_db, &mut step_db)
+                .context("Failed to get volume range")?;
+        }
+
+        Ok(VolumeRange {
+            min_db,
+            max_db,
+            step_db: Some(step_db),
+        })
+    }
+
+    fn get_device_id(device: &IMMDevice) -> Result<String> {
+        let id = unsafe { device.GetId() }.context("Failed to get device ID")?;
+        Self::take_string(id).context("Invalid device ID")
+    }
+
+    /// Copy a string the API allocated, and free it
+    fn take_string(value: PWSTR) -> Result<String> {
+        unsafe {
+            let result = value.to_string().context("String is not valid UTF-16");
+            CoTaskMemFree(Some(value.0 as *const _));
+            result
+        }
+    }
+
+    /// Controls of the application sessions (streams) on every active
+    /// endpoint, without the system sounds session
+    fn get_sessions(&self) -> Result<Vec<IAudioSessionControl2>> {
+        let enumerator = Self::get_device_enumerator()?;
+        let collection = unsafe {
+            enumerator
+                .EnumAudioEndpoints(self.data_flow(), DEVICE_STATE_ACTIVE)
+                .with_context(|| format!("Failed to enumerate {} devices", self.direction))?
+        };
+        let count = unsafe { collection.GetCount() }.context("Failed to count devices")?;
+
+        let mut sessions = Vec::new();
+        for index in 0..count {
+            let device = unsafe { collection.Item(index) }.context("Failed to get device")?;
+            let manager: IAudioSessionManager2 = unsafe { device.Activate(CLSCTX_ALL, None) }
+                .context("Failed to activate audio session manager")?;
+            let list = unsafe { manager.GetSessionEnumerator() }
+                .context("Failed to enumerate audio sessions")?;
+            let session_count =
+                unsafe { list.GetCount() }.context("Failed to count audio sessions")?;
+
+            for session in 0..session_count {
+                let control: IAudioSessionControl2 = unsafe { list.GetSession(session) }
+                    .context("Failed to get audio session")?
+                    .cast()
+                    .context("Failed to query audio session")?;
+                if unsafe { control.IsSystemSoundsSession() } == S_OK {
+                    continue;
+                }
+                sessions.push(control);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Name of the application owning `session`: its display name, or else
+    /// the executable name of its process
+    fn get_application_name(session: &IAudioSessionControl2) -> String {
+        let display_name = unsafe { session.GetDisplayName() }
+            .ok()
+            .and_then(|name| Self::take_string(name).ok())
+            // Resource references such as "@%SystemRoot%\..." are not names
+            .filter(|name| !name.is_empty() && !name.starts_with('@'));
+        if let Some(name) = display_name {
+            return name;
+        }
+
+        let process_id = unsafe { session.GetProcessId() }.unwrap_or_default();
+        Self::get_process_name(process_id).unwrap_or_else(|| format!("process {}", process_id))
+    }
+
+    fn get_process_name(process_id: u32) -> Option<String> {
+        let mut buffer = [0u16; 260];
+        let mut size = buffer.len() as u32;
+        unsafe {
+            let process = OpenProcess(
+                PROCESS_QUERY_LIMITED_INFORMATION,
+                BOOL::from(false),
+                process_id,
+            )
+            .ok()?;
+            let result = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buffer.as_mut_ptr()),
+                &mut size,
+            );
+            let _ = CloseHandle(process);
+            result.ok()?;
+        }
+
+        let path = String::from_utf16_lossy(&buffer[..size as usize]);
+        Path::new(&path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    }
+
+    fn get_friendly_name(device: &IMMDevice) -> Result<String> {
+        unsafe {
+            let store = device
+                .OpenPropertyStore(STGM_READ)
+                .context("Failed to open device property store")?;
+            let name = store
+                .GetValue(&PKEY_Device_FriendlyName)
+                .context("Failed to read device friendly name")?;
+            Ok(name.to_string())
+        }
+    }
+}
+
+impl VolumeBackend for AudioController {
+    fn name(&self) -> &'static str {
+        "wasapi"
+    }
+
+    fn get_volume(&self, endpoint: &Endpoint) -> Result<f32> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            volume
+                .GetMasterVolumeLevelScalar()
+                .context("Failed to get volume level")
+        }
+    }
+
+    fn set_volume(&self, endpoint: &Endpoint, target_volume: f32) -> Result<()> {
+        check_volume_range(target_volume)?;
+
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            volume
+                .SetMasterVolumeLevelScalar(target_volume, &EVENT_CONTEXT)
+                .context("Failed to set volume level")?;
+        }
+
+        Ok(())
+    }
+
+    fn get_volume_db(&self, endpoint: &Endpoint) -> Result<f32> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            volume
+                .GetMasterVolumeLevel()
+                .context("Failed to get volume level")
+        }
+    }
+
+    fn set_volume_db(&self, endpoint: &Endpoint, db: f32) -> Result<()> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+        Self::read_volume_range(&volume)?.check(db)?;
+
+        unsafe {
+            volume
+                .SetMasterVolumeLevel(db, &EVENT_CONTEXT)
+                .context("Failed to set volume level")?;
+        }
+
+        Ok(())
+    }
+
+    fn volume_range(&self, endpoint: &Endpoint) -> Result<VolumeRange> {
+        Self::read_volume_range(&self.get_endpoint_volume_control(endpoint)?)
+    }
+
+    fn get_channel_volumes(&self, endpoint: &Endpoint) -> Result<Vec<ChannelVolume>> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            let count = volume
+                .GetChannelCount()
+                .context("Failed to get channel count")?;
+            (0..count)
+                .map(|channel| {
+                    Ok(ChannelVolume {
+                        name: format!("channel {}", channel + 1),
+                        volume: volume
+                            .GetChannelVolumeLevelScalar(channel)
+                            .context("Failed to get channel volume level")?,
+                        db: volume.GetChannelVolumeLevel(channel).ok(),
+                    })
+                })
+                .collect()
+        }
+    }
+
+    fn set_channel_volumes(&self, endpoint: &Endpoint, volumes: &[f32]) -> Result<()> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            let count = volume
+                .GetChannelCount()
+                .context("Failed to get channel count")?;
+            check_channel_volumes(volumes, count as usize)?;
+
+            for (channel, &level) in (0..count).zip(volumes) {
+                volume
+                    .SetChannelVolumeLevelScalar(channel, level, &EVENT_CONTEXT)
+                    .context("Failed to set channel volume level")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_mute(&self, endpoint: &Endpoint) -> Result<bool> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            Ok(volume
+                .GetMute()
+                .context("Failed to get mute state")?
+                .as_bool())
+        }
+    }
+
+    fn set_mute(&self, endpoint: &Endpoint, muted: bool) -> Result<()> {
+        let volume = self.get_endpoint_volume_control(endpoint)?;
+
+        unsafe {
+            volume
+                .SetMute(BOOL::from(muted), &EVENT_CONTEXT)
+                .context("Failed to set mute state")
+        }
+    }
+
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let enumerator = Self::get_device_enumerator()?;
+
+        // A role without a default endpoint is not an error when listing
+        let default_ids: Vec<(Role, String)> = Role::ALL
+            .iter()
+            .filter_map(|&role| {
+                let device = self.get_default_endpoint(&enumerator, role).ok()?;
+                Some((role, Self::get_device_id(&device).ok()?))
+            })
+            .collect();
+
+        // Devices that were removed from the system (NOTPRESENT) are skipped
+        let state_mask = DEVICE_STATE(
+            DEVICE_STATE_ACTIVE.0 | DEVICE_STATE_DISABLED.0 | DEVICE_STATE_UNPLUGGED.0,
+        );
+        let collection = unsafe {
+            enumerator
+                .EnumAudioEndpoints(self.data_flow(), state_mask)
+                .with_context(|| format!("Failed to enumerate {} devices", self.direction))?
+        };
+        let count = unsafe { collection.GetCount() }.context("Failed to count devices")?;
+
+        let mut devices = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let device = unsafe { collection.Item(index) }.context("Failed to get device")?;
+            let id = Self::get_device_id(&device)?;
+
+            let state = match unsafe { device.GetState() }.context("Failed to get device state")? {
+                DEVICE_STATE_ACTIVE => DeviceState::Active,
+                DEVICE_STATE_DISABLED => DeviceState::Disabled,
+                _ => DeviceState::Unplugged,
+            };
+
+            // Only active endpoints expose a volume control
+            let volume_control = match state {
+                DeviceState::Active => Self::get_volume_control(&device).ok(),
+                _ => None,
+            };
+            let volume = volume_control
+                .as_ref()
+                .and_then(|control| unsafe { control.GetMasterVolumeLevelScalar() }.ok());
+            let muted = volume_control
+                .as_ref()
+                .and_then(|control| unsafe { control.GetMute() }.ok())
+                .map(|muted| muted.as_bool());
+
+            devices.push(DeviceInfo {
+                name: Self::get_friendly_name(&device)?,
+                state,
+                volume,
+                muted,
+                default_for: default_ids
+                    .iter()
+                    .filter(|(_, default_id)| *default_id == id)
+                    .map(|(role, _)| *role)
+                    .collect(),
+                id,
+            });
+        }
+
+        Ok(devices)
+    }
+
+    fn list_streams(&self) -> Result<Vec<StreamInfo>> {
+        let mut streams = Vec::new();
+        for session in self.get_sessions()? {
+            let id = Self::take_string(
+                unsafe { session.GetSessionInstanceIdentifier() }
+                    .context("Failed to get session ID")?,
+            )?;
+            let volume: ISimpleAudioVolume =
+                session.cast().context("Failed to query session volume")?;
+
+            streams.push(StreamInfo {
+                id,
+                application: Self::get_application_name(&session),
+                volume: unsafe { volume.GetMasterVolume() }
+                    .context("Failed to get session volume")?,
+                muted: unsafe { volume.GetMute() }
+                    .ok()
+                    .map(|muted| muted.as_bool()),
+            });
+        }
+        Ok(streams)
+    }
+
+    fn set_stream_volume(&self, id: &str, volume: f32) -> Result<()> {
+        check_volume_range(volume)?;
+
+        for session in self.get_sessions()? {
+            let session_id = unsafe { session.GetSessionInstanceIdentifier() }
+                .ok()
+                .and_then(|session_id| Self::take_string(session_id).ok());
+            if session_id.as_deref() != Some(id) {
+                continue;
+            }
+
+            let control: ISimpleAudioVolume =
+                session.cast().context("Failed to query session volume")?;
+            return unsafe {
+                control
+                    .SetMasterVolume(volume, &EVENT_CONTEXT)
+                    .context("Failed to set session volume")
+            };
+        }
+        anyhow::bail!("Stream '{}' not found", id)
+    }
+
+    /// Only endpoint changes are reported; session (stream) volumes are
+    /// picked up by the periodic resynchronization of the watcher, and the
+    /// volumes of endpoints that arrive later by watching again
+    fn watch(&self, events: Sender<VolumeEvent>) -> Result<Watch> {
+        let enumerator = Self::get_device_enumerator()?;
+        let client: IMMNotificationClient = DeviceCallback {
+            flow: self.data_flow(),
+            events: events.clone(),
+        }
+        .into();
+        unsafe { enumerator.RegisterEndpointNotificationCallback(&client) }
+            .context("Failed to register for device notifications")?;
+        let devices = DeviceRegistration {
+            enumerator: enumerator.clone(),
+            client,
+        };
+
+        let collection = unsafe {
+            enumerator
+                .EnumAudioEndpoints(self.data_flow(), DEVICE_STATE_ACTIVE)
+                .with_context(|| format!("Failed to enumerate {} devices", self.direction))?
+        };
+        let count = unsafe { collection.GetCount() }.context("Failed to count devices")?;
+
+        let mut registrations = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let device = unsafe { collection.Item(index) }.context("Failed to get device")?;
+            let volume = Self::get_volume_control(&device)?;
+            let callback: IAudioEndpointVolumeCallback = VolumeCallback {
+                device: Self::get_device_id(&device)?,
+                events: events.clone(),
+            }
+            .into();
+            unsafe { volume.RegisterControlChangeNotify(&callback) }
+                .context("Failed to register for volume notifications")?;
+            registrations.push(Registration { volume, callback });
+        }
+
+        Ok(Watch::new((devices, registrations)))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mute: true,
+            device_list: true,
+            streams: true,
+        }
+    }
+}
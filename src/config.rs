@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::audio::Role;
+use crate::volume::{Ramp, VolumeChange, VolumeLevel};
 
 const APPLICATION: &str = "mic-volume-control";
 
@@ -16,113 +19,806 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub quiet: bool,
 
+    /// Show which volumes, files and tasks a command would change, without
+    /// changing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Audio backend to use, overriding `backend` in the config file
+    #[arg(long, global = true, value_enum, env = "MIC_VOLUME_CONTROL_BACKEND")]
+    pub backend: Option<BackendKind>,
+
+    /// Default-device role whose microphone to control, overriding `role` in
+    /// the config file; saved by `install`
+    #[arg(long, global = true, value_enum)]
+    pub role: Option<DeviceRole>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// WASAPI on Windows; on Linux the first of PipeWire, PulseAudio and the
+    /// ALSA mixer that is available
+    #[default]
+    Auto,
+    /// PulseAudio (or PipeWire's PulseAudio layer) through `pactl` (Linux)
+    Pulse,
+    /// Native PipeWire through `pw-dump` and `pw-cli` (Linux)
+    Pipewire,
+    /// ALSA simple mixer through `amixer`, configured in `[alsa]` (Linux)
+    Alsa,
+    /// In-memory devices and scheduler for testing, scripted through
+    /// `MIC_VOLUME_CONTROL_MOCK`
+    #[value(hide = true)]
+    Mock,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BackendKind::Auto => "auto",
+            BackendKind::Pulse => "pulse",
+            BackendKind::Pipewire => "pipewire",
+            BackendKind::Alsa => "alsa",
+            BackendKind::Mock => "mock",
+        })
+    }
+}
+
+/// Default device(s) controlled when no `device` is selected
+///
+/// Windows keeps a separate default microphone per role; elsewhere every role
+/// has the same default device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceRole {
+    /// Default device for games and system sounds
+    #[default]
+    Console,
+    /// Default communications device, used by Teams, Discord and other
+    /// calling apps
+    Communications,
+    /// Default device for music and video
+    Multimedia,
+    /// The default devices of every role
+    All,
+}
+
+impl DeviceRole {
+    /// Roles whose default devices are controlled
+    pub fn roles(self) -> &'static [Role] {
+        match self {
+            DeviceRole::Console => &[Role::Console],
+            DeviceRole::Communications => &[Role::Communications],
+            DeviceRole::Multimedia => &[Role::Multimedia],
+            DeviceRole::All => &Role::ALL,
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeviceRole::Console => "console",
+            DeviceRole::Communications => "communications",
+            DeviceRole::Multimedia => "multimedia",
+            DeviceRole::All => "all",
+        })
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Get or set microphone volume
     Volume {
-        /// Volume level to set (0-100). If not specified, shows current volume
-        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
-        level: Option<u8>,
+        /// Volume to set (80, 80%, 0.8 or 6dB; a negative gain with --db), or
+        /// a step from the current volume in percent (+5, -5%) or dB (+1.5dB,
+        /// -3dB). If not specified, shows current volume
+        #[arg(allow_hyphen_values = true)]
+        level: Option<VolumeChange>,
+
+        /// Volume to set in dB, within the device's range, instead of a level
+        #[arg(long, allow_negative_numbers = true, conflicts_with = "level")]
+        db: Option<f32>,
+
+        /// Device to control (ID or name pattern), overriding `device` in the
+        /// config file
+        #[arg(short, long)]
+        device: Option<String>,
     },
 
-    /// Install Windows Task Scheduler task for automatic volume control
+    /// Install a scheduled task for automatic volume control (Task Scheduler on
+    /// Windows, systemd user timer on Linux)
     Install {
-        /// Target volume level (0-100)
-        #[arg(short, long, default_value = "95")]
-        volume: u8,
+        /// Target volume (80, 80%, 0.8 or -6dB)
+        #[arg(short, long, default_value = "95", allow_hyphen_values = true)]
+        volume: VolumeLevel,
+
+        /// Target volume in dB, instead of a level
+        #[arg(long, allow_negative_numbers = true, conflicts_with = "volume")]
+        db: Option<f32>,
 
         /// Run interval in minutes
         #[arg(short, long, default_value = "5")]
         interval: u32,
+
+        /// Device to control (ID or name pattern), saved as `device` in the
+        /// config file
+        #[arg(short, long)]
+        device: Option<String>,
     },
 
-    /// Uninstall Windows Task Scheduler task
+    /// Uninstall the scheduled task
     Uninstall,
 
+    /// Keep running and restore the configured targets as soon as something
+    /// changes a volume, instead of on a schedule
+    #[command(visible_alias = "daemon")]
+    Watch,
+
+    /// Keep running and apply the configured targets to microphones as they
+    /// are plugged in or become the default
+    Monitor,
+
+    /// Keep running and hold the microphone at the configured target against
+    /// other applications, backing off when one keeps changing it back
+    Lock {
+        /// Changes within the window that mean another application is
+        /// fighting over the volume
+        #[arg(long, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+        max_changes: u32,
+
+        /// Window in seconds that changes are counted in
+        #[arg(long, default_value = "10")]
+        window: u64,
+
+        /// Seconds to back off on the first fight; doubled on every fight in
+        /// a row
+        #[arg(long, default_value = "5")]
+        backoff: u64,
+
+        /// Longest back-off in seconds
+        #[arg(long, default_value = "300")]
+        max_backoff: u64,
+    },
+
+    /// Mute the microphone
+    Mute {
+        /// Device to mute (ID or name pattern), overriding `device` in the
+        /// config file
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+
+    /// Unmute the microphone
+    Unmute {
+        /// Device to unmute (ID or name pattern), overriding `device` in the
+        /// config file
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+
+    /// Mute the microphone if it is unmuted, and unmute it otherwise
+    ToggleMute {
+        /// Device to toggle (ID or name pattern), overriding `device` in the
+        /// config file
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+
+    /// List the capture streams applications have open, with their volumes
+    Streams,
+
+    /// List capture devices with their state, volume and default roles
+    Devices {
+        /// List output devices instead, e.g. to pick the `[output]` device
+        #[arg(long)]
+        output: bool,
+    },
+
     /// Show current configuration
     Config,
 }
 
+impl Commands {
+    /// Whether the command only reports, and changes no volume, file or task
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Commands::Volume { level, db, .. } => level.is_none() && db.is_none(),
+            Commands::Streams | Commands::Devices { .. } | Commands::Config => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    /// Target volume level (0.0 to 1.0, where 1.0 = 100%)
+    /// Target volume: a scalar (0.0 to 1.0, where 1.0 = 100%), or a string in
+    /// any form the command line accepts ("80%", "-6dB")
     #[serde(default = "default_volume")]
-    pub target_volume: f32,
+    pub target_volume: VolumeLevel,
+
+    /// Target volume in dB, used instead of `target_volume` when set; kept for
+    /// config files written before `target_volume` accepted dB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_db: Option<f32>,
+
+    /// How the microphone targets are enforced: exactly, as a floor or a
+    /// ceiling, or as an allowed range; volumes that comply are not written
+    #[serde(default, skip_serializing_if = "Policy::is_default")]
+    pub policy: Policy,
+
+    /// Lowest microphone volume any command sets; lower targets are raised
+    /// to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_volume: Option<VolumeLevel>,
+
+    /// Highest microphone volume any command sets; higher targets are
+    /// lowered to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_volume: Option<VolumeLevel>,
+
+    /// Largest change of the microphone volume one command makes, in percent
+    /// points or dB; the rest is left to later runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_step_per_run: Option<VolumeLevel>,
+
+    /// Move the microphone volume gradually when a run or `volume` changes
+    /// it (`[ramp]`); set in one jump when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ramp: Option<Ramp>,
 
     /// Task run interval in minutes
     #[serde(default = "default_interval")]
     pub run_interval_minutes: u32,
+
+    /// Mute state enforced with `target_volume` on each run (`false` keeps the
+    /// microphone unmuted); left alone when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+
+    /// Audio backend, unless overridden with `--backend`
+    #[serde(default)]
+    pub backend: BackendKind,
+
+    /// Default-device role whose microphone is controlled when `device` is
+    /// unset
+    #[serde(default)]
+    pub role: DeviceRole,
+
+    /// Capture device to control: an exact device ID or a friendly-name
+    /// pattern (see `devices`); the system default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+
+    /// Control the default device when `device` matches no present device,
+    /// instead of failing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback_to_default: bool,
+
+    /// Per-channel levels, or `"balanced"` to keep every channel at the
+    /// master volume; channels are left alone when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelTarget>,
+
+    /// Per-device targets (`[[devices]]`), enforced alongside `target_volume`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceTarget>,
+
+    /// Per-application stream targets (`[[streams]]`), enforced alongside
+    /// `target_volume`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub streams: Vec<StreamTarget>,
+
+    /// Output device target (`[output]`), enforced by the same runs as the
+    /// microphone; output devices are left alone when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputConfig>,
+
+    /// Settings for the `alsa` backend
+    #[serde(default, skip_serializing_if = "AlsaConfig::is_empty")]
+    pub alsa: AlsaConfig,
 }
 
-fn default_volume() -> f32 {
-    0.95
+/// Target volume for the speakers or headphones
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// Target volume, in the same forms as the top-level `target_volume`
+    pub target_volume: VolumeLevel,
+
+    /// Mute state enforced with `target_volume`; left alone when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+
+    /// Output device: an exact device ID or a friendly-name pattern (see
+    /// `devices --output`); the system default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Volume for the capture streams of the applications a `[[streams]]` entry
+/// matches
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamTarget {
+    /// Application name pattern, matched like device names
+    pub application: String,
+
+    /// Stream volume (0.0 - 1.0), in the scalar forms of `target_volume`;
+    /// streams have no dB scale
+    #[serde(deserialize_with = "deserialize_scalar")]
+    pub target_volume: f32,
+}
+
+/// Deserialize a `VolumeLevel` that must be a scalar
+fn deserialize_scalar<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match VolumeLevel::deserialize(deserializer)? {
+        VolumeLevel::Scalar(volume) => Ok(volume),
+        VolumeLevel::Db(_) => Err(serde::de::Error::custom(
+            "stream volumes are percentages or fractions, not dB",
+        )),
+    }
+}
+
+/// Target volume for the devices a `[[devices]]` entry matches
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DeviceTarget {
+    /// Exact device ID or friendly-name pattern; a pattern may match several
+    /// devices
+    pub device: String,
+
+    /// Target volume, in the same forms as the top-level `target_volume`
+    pub target_volume: VolumeLevel,
+
+    /// Mute state to enforce; the top-level `muted` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+
+    /// Channel levels to enforce; the top-level `channels` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelTarget>,
+}
+
+/// Per-channel volumes enforced after the master volume
+///
+/// Written as `"balanced"` or as a list of levels, one per channel in the
+/// device's channel order (`[0.8, "75%"]`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelTarget {
+    /// Every channel at the master volume, undoing drift between channels
+    Balanced,
+    /// One volume scalar (0.0 to 1.0) per channel
+    Levels(Vec<f32>),
+}
+
+impl std::fmt::Display for ChannelTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelTarget::Balanced => f.write_str("balanced"),
+            ChannelTarget::Levels(levels) => {
+                let levels: Vec<String> = levels
+                    .iter()
+                    .map(|&level| VolumeLevel::Scalar(level).to_string())
+                    .collect();
+                f.write_str(&levels.join(", "))
+            }
+        }
+    }
+}
+
+impl Serialize for ChannelTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ChannelTarget::Balanced => serializer.serialize_str("balanced"),
+            ChannelTarget::Levels(levels) => levels.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ChannelTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{Error, SeqAccess, Visitor};
+
+        struct ChannelTargetVisitor;
+
+        impl<'de> Visitor<'de> for ChannelTargetVisitor {
+            type Value = ChannelTarget;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("\"balanced\" or a list of channel levels")
+            }
+
+            fn visit_str<E: Error>(self, mode: &str) -> Result<ChannelTarget, E> {
+                match mode {
+                    "balanced" => Ok(ChannelTarget::Balanced),
+                    _ => Err(E::custom(format!(
+                        "invalid channels '{}': expected \"balanced\" or a list of channel \
+                         levels",
+                        mode
+                    ))),
+                }
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ChannelTarget, A::Error> {
+                let mut levels = Vec::new();
+                while let Some(level) = seq.next_element::<VolumeLevel>()? {
+                    match level {
+                        VolumeLevel::Scalar(volume) => levels.push(volume),
+                        VolumeLevel::Db(_) => {
+                            return Err(A::Error::custom(
+                                "channel levels are percentages or fractions, not dB",
+                            ));
+                        }
+                    }
+                }
+                if levels.is_empty() {
+                    return Err(A::Error::custom("channels lists no channel levels"));
+                }
+                Ok(ChannelTarget::Levels(levels))
+            }
+        }
+
+        deserializer.deserialize_any(ChannelTargetVisitor)
+    }
+}
+
+/// How far a microphone volume may be from its target before a run sets it
+///
+/// Written as a table tagged by `mode`, e.g. `policy = { mode = "floor" }` or
+/// `policy = { mode = "range", min = "70%", max = "90%" }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Policy {
+    /// Set the target unless the volume is within `tolerance` of it; a
+    /// tolerance is in the unit of the target (percent points or dB), and
+    /// the backend's rounding when unset
+    Exact {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tolerance: Option<VolumeLevel>,
+    },
+    /// Only raise volumes below the target
+    Floor,
+    /// Only lower volumes above the target
+    Ceiling,
+    /// Leave volumes from `min` to `max` alone, and move the others to the
+    /// nearest bound; the target itself is not used
+    Range { min: VolumeLevel, max: VolumeLevel },
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::Exact { tolerance: None }
+    }
+}
+
+impl Policy {
+    fn is_default(&self) -> bool {
+        *self == Policy::default()
+    }
+
+    /// Where the policy wants a volume for the target `level`, completing
+    /// "volume already ..."
+    pub fn compliance(&self, level: VolumeLevel) -> String {
+        match self {
+            Policy::Exact { tolerance: None } => format!("at {}", level),
+            Policy::Exact {
+                tolerance: Some(tolerance),
+            } => format!("within {} of {}", tolerance, level),
+            Policy::Floor => format!("at or above {}", level),
+            Policy::Ceiling => format!("at or below {}", level),
+            Policy::Range { min, max } => format!("between {} and {}", min, max),
+        }
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Exact { tolerance: None } => f.write_str("exact"),
+            Policy::Exact {
+                tolerance: Some(tolerance),
+            } => write!(f, "exact within {}", tolerance),
+            Policy::Floor => f.write_str("floor"),
+            Policy::Ceiling => f.write_str("ceiling"),
+            Policy::Range { min, max } => write!(f, "range {} to {}", min, max),
+        }
+    }
+}
+
+/// ALSA simple mixer settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AlsaConfig {
+    /// Card index or name as accepted by `amixer -c`; the `default` device when
+    /// unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub card: Option<String>,
+
+    /// Simple mixer control; the first of "Capture" and "Mic" when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control: Option<String>,
+
+    /// Simple mixer control for the `[output]` volume; the first of "Master",
+    /// "PCM", "Speaker" and "Headphone" when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playback_control: Option<String>,
+}
+
+impl AlsaConfig {
+    fn is_empty(&self) -> bool {
+        self.card.is_none() && self.control.is_none() && self.playback_control.is_none()
+    }
+}
+
+fn default_volume() -> VolumeLevel {
+    VolumeLevel::Scalar(0.95)
 }
 
 fn default_interval() -> u32 {
     5
 }
 
+/// Base directory for user configuration (`$XDG_CONFIG_HOME` or `~/.config`)
+#[cfg(not(windows))]
+pub fn xdg_config_home() -> Result<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => {
+            let home = std::env::var_os("HOME").context("HOME environment variable not found")?;
+            Ok(PathBuf::from(home).join(".config"))
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             target_volume: default_volume(),
+            target_db: None,
+            policy: Policy::default(),
+            min_volume: None,
+            max_volume: None,
+            max_step_per_run: None,
+            ramp: None,
             run_interval_minutes: default_interval(),
+            muted: None,
+            backend: BackendKind::default(),
+            role: DeviceRole::default(),
+            device: None,
+            fallback_to_default: false,
+            channels: None,
+            devices: Vec::new(),
+            streams: Vec::new(),
+            output: None,
+            alsa: AlsaConfig::default(),
         }
     }
 }
 
 impl Config {
-    pub fn load_from_file() -> Result<Self> {
-        let config_path = Self::get_config_path()?;
-
+    /// Load configuration from `config_path`, falling back to defaults when the
+    /// file does not exist
+    pub fn load(config_path: &Path) -> Result<Self> {
         if !config_path.exists() {
             return Ok(Self::default());
         }
 
-        let content = fs::read_to_string(&config_path).context("Failed to read config file")?;
+        let content = fs::read_to_string(config_path).context("Failed to read config file")?;
         let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
+        config.validate().context("Invalid config file")?;
 
         Ok(config)
     }
 
-    pub fn save(&self) -> Result<()> {
-        let config_path = Self::get_config_path()?;
+    /// Check the safety limits and the policy range against each other and
+    /// the limits against the channel levels, which are not clamped at
+    /// runtime
+    fn validate(&self) -> Result<()> {
+        if let (Some(min), Some(max)) = (self.min_volume, self.max_volume) {
+            Self::check_bounds(("min_volume", min), ("max_volume", max))?;
+        }
+        if let Policy::Range { min, max } = self.policy {
+            Self::check_bounds(("policy min", min), ("policy max", max))?;
+        }
+        if let Some(step) = self.max_step_per_run
+            && (!step.value().is_finite() || step.value() <= 0.0)
+        {
+            anyhow::bail!("max_step_per_run must be above zero, not {}", step);
+        }
+
+        let channels = self
+            .devices
+            .iter()
+            .filter_map(|target| target.channels.as_ref())
+            .chain(&self.channels);
+        for channels in channels {
+            let ChannelTarget::Levels(levels) = channels else {
+                continue;
+            };
+            let limits = [
+                ("min_volume", self.min_volume, -1.0),
+                ("max_volume", self.max_volume, 1.0),
+            ];
+            for (name, limit, side) in limits {
+                let Some(limit) = limit else {
+                    continue;
+                };
+                let VolumeLevel::Scalar(bound) = limit else {
+                    anyhow::bail!(
+                        "{} {} cannot be checked against channel levels, which are \
+                         percentages",
+                        name,
+                        limit
+                    );
+                };
+                let outside = levels.iter().find(|&&level| (level - bound) * side > 0.0);
+                if let Some(&level) = outside {
+                    anyhow::bail!(
+                        "Channel level {} is outside {} {}",
+                        VolumeLevel::Scalar(level),
+                        name,
+                        limit
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail unless the `low` and `high` bounds are in the same unit, with
+    /// `low` not above `high`
+    fn check_bounds(low: (&str, VolumeLevel), high: (&str, VolumeLevel)) -> Result<()> {
+        let ((low_name, min), (high_name, max)) = (low, high);
+        match (min, max) {
+            (VolumeLevel::Scalar(low), VolumeLevel::Scalar(high))
+            | (VolumeLevel::Db(low), VolumeLevel::Db(high)) => {
+                if low > high {
+                    anyhow::bail!("{} {} is above {} {}", low_name, min, high_name, max);
+                }
+            }
+            _ => anyhow::bail!(
+                "{} {} and {} {} are in different units",
+                low_name,
+                min,
+                high_name,
+                max
+            ),
+        }
+        Ok(())
+    }
 
+    pub fn save_to(&self, config_path: &Path) -> Result<()> {
         // Ensure directory exists
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent).context("Failed to create config directory")?;
         }
 
         let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
-        fs::write(&config_path, content).context("Failed to write config file")?;
+        fs::write(config_path, content).context("Failed to write config file")?;
 
         Ok(())
     }
 
     pub fn get_config_path() -> Result<PathBuf> {
+        let mut path = Self::get_config_dir()?;
+        path.push("config.toml");
+
+        Ok(path)
+    }
+
+    /// Per-user application directory (`%APPDATA%\mic-volume-control`)
+    #[cfg(windows)]
+    pub fn get_config_dir() -> Result<PathBuf> {
         let app_data =
             std::env::var("APPDATA").context("APPDATA environment variable not found")?;
 
         let mut path = PathBuf::from(app_data);
         path.push(APPLICATION);
-        path.push("config.toml");
 
         Ok(path)
     }
 
-    /// Display current configuration
-    pub fn display(&self) {
+    /// Per-user application directory (`$XDG_CONFIG_HOME/mic-volume-control`,
+    /// falling back to `~/.config/mic-volume-control`)
+    #[cfg(not(windows))]
+    pub fn get_config_dir() -> Result<PathBuf> {
+        let mut path = xdg_config_home()?;
+        path.push(APPLICATION);
+
+        Ok(path)
+    }
+
+    /// Volume the scheduled task enforces
+    pub fn target(&self) -> VolumeLevel {
+        match self.target_db {
+            Some(db) => VolumeLevel::Db(db),
+            None => self.target_volume,
+        }
+    }
+
+    /// Display current configuration loaded from `config_path`
+    pub fn display(&self, config_path: &Path) {
         println!("Current Configuration:");
-        println!("  Target Volume: {:.0}%", self.target_volume * 100.0);
+        println!("  Target Volume: {}", self.target());
+        if !self.policy.is_default() {
+            println!("  Policy: {}", self.policy);
+        }
+        match (self.min_volume, self.max_volume) {
+            (Some(min), Some(max)) => println!("  Volume Limits: {} to {}", min, max),
+            (Some(min), None) => println!("  Volume Limits: at least {}", min),
+            (None, Some(max)) => println!("  Volume Limits: at most {}", max),
+            (None, None) => {}
+        }
+        if let Some(step) = self.max_step_per_run {
+            println!("  Max Step per Run: {}", step);
+        }
+        if let Some(ramp) = &self.ramp {
+            println!("  Ramp: {}", ramp);
+        }
         println!("  Run Interval: {} minutes", self.run_interval_minutes);
-
-        if let Ok(path) = Self::get_config_path() {
-            println!("\nConfig file: {}", path.display());
+        match self.muted {
+            Some(true) => println!("  Mute: keep muted"),
+            Some(false) => println!("  Mute: keep unmuted"),
+            None => println!("  Mute: not enforced"),
+        }
+        println!("  Backend: {}", self.backend);
+        match &self.device {
+            Some(device) if self.fallback_to_default => {
+                println!("  Device: {} (falls back to default)", device)
+            }
+            Some(device) => println!("  Device: {}", device),
+            None => println!("  Device: system default ({} role)", self.role),
+        }
+        if let Some(channels) = &self.channels {
+            println!("  Channels: {}", channels);
+        }
+        for target in &self.devices {
+            let mute_note = match target.muted {
+                Some(true) => ", muted",
+                Some(false) => ", unmuted",
+                None => "",
+            };
+            let channels_note = match &target.channels {
+                Some(channels) => format!(", channels {}", channels),
+                None => String::new(),
+            };
+            println!(
+                "  Device Target: {} -> {}{}{}",
+                target.device, target.target_volume, mute_note, channels_note
+            );
+        }
+        for target in &self.streams {
+            println!(
+                "  Stream Target: {} -> {}",
+                target.application,
+                VolumeLevel::Scalar(target.target_volume)
+            );
+        }
+        if let Some(output) = &self.output {
+            let mute_note = match output.muted {
+                Some(true) => ", muted",
+                Some(false) => ", unmuted",
+                None => "",
+            };
+            println!(
+                "  Output: {} -> {}{}",
+                output.device.as_deref().unwrap_or("system default"),
+                output.target_volume,
+                mute_note
+            );
+        }
+        if let Some(card) = &self.alsa.card {
+            println!("  ALSA Card: {}", card);
         }
+        if let Some(control) = &self.alsa.control {
+            println!("  ALSA Control: {}", control);
+        }
+        if let Some(control) = &self.alsa.playback_control {
+            println!("  ALSA Playback Control: {}", control);
+        }
+
+        println!("\nConfig file: {}", config_path.display());
     }
 }
 
@@ -133,7 +829,7 @@ mod tests {
     #[test]
     fn test_default_config() {
         let config = Config::default();
-        assert_eq!(config.target_volume, 0.95);
+        assert_eq!(config.target_volume, VolumeLevel::Scalar(0.95));
         assert_eq!(config.run_interval_minutes, 5);
     }
 
@@ -155,13 +851,306 @@ mod tests {
         // Config with only target_volume (old format)
         let partial = "target_volume = 0.8";
         let config: Config = toml::from_str(partial).unwrap();
-        assert_eq!(config.target_volume, 0.8);
+        assert_eq!(config.target_volume, VolumeLevel::Scalar(0.8));
         assert_eq!(config.run_interval_minutes, 5); // default value
 
         // Empty config
         let empty = "";
         let config: Config = toml::from_str(empty).unwrap();
-        assert_eq!(config.target_volume, 0.95); // default value
+        assert_eq!(config.target_volume, VolumeLevel::Scalar(0.95)); // default value
         assert_eq!(config.run_interval_minutes, 5); // default value
+        assert_eq!(config.muted, None); // mute state not enforced
+        assert_eq!(config.target(), VolumeLevel::Scalar(0.95));
+
+        let config: Config = toml::from_str("target_db = -12.5").unwrap();
+        assert_eq!(config.target(), VolumeLevel::Db(-12.5));
+
+        // Any form accepted on the command line
+        let config: Config = toml::from_str("target_volume = \"-9dB\"").unwrap();
+        assert_eq!(config.target(), VolumeLevel::Db(-9.0));
+        let config: Config = toml::from_str("target_volume = 80").unwrap();
+        assert_eq!(config.target(), VolumeLevel::Scalar(0.8));
+        let err = toml::from_str::<Config>("target_volume = \"loud\"").unwrap_err();
+        assert!(err.to_string().contains("expected a percentage"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("config.toml");
+
+        assert_eq!(
+            Config::load(&path).unwrap().target_volume,
+            VolumeLevel::Scalar(0.95)
+        );
+
+        let config = Config {
+            target_volume: VolumeLevel::Scalar(0.6),
+            run_interval_minutes: 15,
+            ..Config::default()
+        };
+        config.save_to(&path).unwrap();
+
+        let loaded = Config::load(&path).unwrap();
+        assert_eq!(loaded.target_volume, VolumeLevel::Scalar(0.6));
+        assert_eq!(loaded.run_interval_minutes, 15);
+    }
+
+    #[test]
+    fn test_alsa_backend_config() {
+        let content = r#"
+backend = "alsa"
+
+[alsa]
+card = "1"
+control = "Mic"
+"#;
+        let config: Config = toml::from_str(content).unwrap();
+        assert_eq!(config.backend, BackendKind::Alsa);
+        assert_eq!(config.alsa.card.as_deref(), Some("1"));
+        assert_eq!(config.alsa.control.as_deref(), Some("Mic"));
+
+        let serialized = toml::to_string(&Config::default()).unwrap();
+        assert!(!serialized.contains("[alsa]"));
+        assert!(toml::from_str::<Config>("backend = \"oss\"").is_err());
+    }
+
+    #[test]
+    fn test_channel_targets() {
+        let config: Config = toml::from_str("channels = \"balanced\"").unwrap();
+        assert_eq!(config.channels, Some(ChannelTarget::Balanced));
+
+        let content = "channels = [0.8, \"75%\", 70]\n[[devices]]\ndevice = \"USB\"\ntarget_volume = 0.5\nchannels = \"balanced\"\n";
+        let config: Config = toml::from_str(content).unwrap();
+        assert_eq!(
+            config.channels,
+            Some(ChannelTarget::Levels(vec![0.8, 0.75, 0.7]))
+        );
+        assert_eq!(config.devices[0].channels, Some(ChannelTarget::Balanced));
+        assert_eq!(
+            config.channels.as_ref().unwrap().to_string(),
+            "80%, 75%, 70%"
+        );
+
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.channels, config.channels);
+        assert_eq!(reloaded.devices, config.devices);
+
+        for (invalid, expected) in [
+            ("channels = \"even\"", "expected \"balanced\""),
+            ("channels = []", "no channel levels"),
+            ("channels = [\"-6dB\"]", "not dB"),
+            ("channels = [120]", "between 0 and 100"),
+        ] {
+            let err = toml::from_str::<Config>(invalid).unwrap_err().to_string();
+            assert!(err.contains(expected), "{}: {}", invalid, err);
+        }
+    }
+
+    #[test]
+    fn test_role_config() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.role, DeviceRole::Console);
+        assert_eq!(config.role.roles(), [Role::Console]);
+
+        let config: Config = toml::from_str("role = \"communications\"").unwrap();
+        assert_eq!(config.role, DeviceRole::Communications);
+        let config: Config = toml::from_str("role = \"all\"").unwrap();
+        assert_eq!(config.role.roles(), Role::ALL);
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.role, DeviceRole::All);
+
+        assert!(toml::from_str::<Config>("role = \"default\"").is_err());
+    }
+
+    #[test]
+    fn test_stream_targets() {
+        let content = r#"
+[[streams]]
+application = "Firefox"
+target_volume = "90%"
+
+[[streams]]
+application = "*zoom*"
+target_volume = 1.0
+"#;
+        let config: Config = toml::from_str(content).unwrap();
+        assert_eq!(config.streams.len(), 2);
+        assert_eq!(config.streams[0].target_volume, 0.9);
+        assert_eq!(config.streams[1].application, "*zoom*");
+
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.streams, config.streams);
+
+        let err = toml::from_str::<Config>(
+            "[[streams]]\napplication = \"Firefox\"\ntarget_volume = \"-6dB\"",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not dB"));
+    }
+
+    #[test]
+    fn test_output_config() {
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.output.is_none());
+        assert!(!toml::to_string(&config).unwrap().contains("[output]"));
+
+        let content = r#"
+target_volume = 0.9
+
+[output]
+target_volume = "-12dB"
+device = "Speakers*"
+"#;
+        let config: Config = toml::from_str(content).unwrap();
+        let output = config.output.as_ref().unwrap();
+        assert_eq!(output.target_volume, VolumeLevel::Db(-12.0));
+        assert_eq!(output.device.as_deref(), Some("Speakers*"));
+        assert_eq!(output.muted, None);
+
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.output, config.output);
+
+        assert!(toml::from_str::<Config>("[output]\nmuted = true").is_err());
+    }
+
+    #[test]
+    fn test_device_targets() {
+        let content = r#"
+target_volume = 0.9
+
+[[devices]]
+device = "Microphone Array*"
+target_volume = 0.7
+
+[[devices]]
+device = "Headset"
+target_volume = 0.85
+muted = false
+"#;
+        let config: Config = toml::from_str(content).unwrap();
+        assert_eq!(config.devices.len(), 2);
+        assert_eq!(config.devices[0].device, "Microphone Array*");
+        assert_eq!(config.devices[1].target_volume, VolumeLevel::Scalar(0.85));
+        assert_eq!(config.devices[0].muted, None);
+        assert_eq!(config.devices[1].muted, Some(false));
+
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.devices, config.devices);
+
+        assert!(toml::from_str::<Config>("[[devices]]\ndevice = \"Mic\"").is_err());
+    }
+
+    #[test]
+    fn test_policy_config() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.policy, Policy::Exact { tolerance: None });
+        assert!(!toml::to_string(&config).unwrap().contains("policy"));
+
+        let config: Config =
+            toml::from_str("[policy]\nmode = \"exact\"\ntolerance = \"2%\"").unwrap();
+        assert_eq!(
+            config.policy,
+            Policy::Exact {
+                tolerance: Some(VolumeLevel::Scalar(0.02))
+            }
+        );
+        let config: Config = toml::from_str("policy = { mode = \"floor\" }").unwrap();
+        assert_eq!(config.policy, Policy::Floor);
+        let config: Config =
+            toml::from_str("policy = { mode = \"range\", min = \"70%\", max = 0.9 }").unwrap();
+        assert_eq!(
+            config.policy,
+            Policy::Range {
+                min: VolumeLevel::Scalar(0.7),
+                max: VolumeLevel::Scalar(0.9)
+            }
+        );
+        assert_eq!(
+            config.policy.compliance(VolumeLevel::Scalar(0.8)),
+            "between 70% and 90%"
+        );
+
+        let reloaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.policy, config.policy);
+
+        assert!(toml::from_str::<Config>("policy = { mode = \"range\", min = 0.7 }").is_err());
+        assert!(toml::from_str::<Config>("policy = { mode = \"loose\" }").is_err());
+
+        // A range must be ordered and in one unit
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let load = |content: &str| {
+            fs::write(&path, content).unwrap();
+            Config::load(&path).map_err(|e| format!("{:#}", e))
+        };
+        assert!(load("policy = { mode = \"range\", min = \"-12dB\", max = \"-3dB\" }").is_ok());
+        let err = load("policy = { mode = \"range\", min = 0.9, max = 0.7 }").unwrap_err();
+        assert!(
+            err.contains("policy min 90% is above policy max 70%"),
+            "{}",
+            err
+        );
+        let err = load("policy = { mode = \"range\", min = \"70%\", max = \"-3dB\" }").unwrap_err();
+        assert!(err.contains("are in different units"), "{}", err);
+    }
+
+    #[test]
+    fn test_volume_limits_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let load = |content: &str| {
+            fs::write(&path, content).unwrap();
+            Config::load(&path).map_err(|e| format!("{:#}", e))
+        };
+
+        let config = load("min_volume = 0.2\nmax_volume = \"90%\"\nmax_step_per_run = 10").unwrap();
+        assert_eq!(config.min_volume, Some(VolumeLevel::Scalar(0.2)));
+        assert_eq!(config.max_volume, Some(VolumeLevel::Scalar(0.9)));
+        assert_eq!(config.max_step_per_run, Some(VolumeLevel::Scalar(0.1)));
+        assert!(
+            !toml::to_string(&Config::default())
+                .unwrap()
+                .contains("max_volume")
+        );
+
+        let err = load("min_volume = 0.9\nmax_volume = 0.5").unwrap_err();
+        assert!(
+            err.contains("min_volume 90% is above max_volume 50%"),
+            "{}",
+            err
+        );
+        assert!(load("min_volume = 0.2\nmax_volume = \"-6dB\"").is_err());
+        assert!(load("max_step_per_run = 0").is_err());
+        assert!(load("max_step_per_run = -0.1").is_err());
+        assert!(load("max_step_per_run = \"-3dB\"").is_err());
+        assert!(load("max_step_per_run = nan").is_err());
+
+        // Channel levels are checked when the config is loaded
+        let err = load("max_volume = 0.9\nchannels = [0.8, 1.0]").unwrap_err();
+        assert!(
+            err.contains("Channel level 100% is outside max_volume 90%"),
+            "{}",
+            err
+        );
+        let err = load("max_volume = \"-6dB\"\n[[devices]]\ndevice = \"Mic\"\ntarget_volume = 0.5\nchannels = [0.5]").unwrap_err();
+        assert!(
+            err.contains("cannot be checked against channel levels"),
+            "{}",
+            err
+        );
+        load("min_volume = 0.2\nchannels = \"balanced\"").unwrap();
+    }
+
+    #[test]
+    fn test_device_config() {
+        let config: Config = toml::from_str("device = \"Yeti*\"").unwrap();
+        assert_eq!(config.device.as_deref(), Some("Yeti*"));
+        assert!(!config.fallback_to_default);
+
+        let serialized = toml::to_string(&Config::default()).unwrap();
+        assert!(!serialized.contains("device"));
+        assert!(!serialized.contains("[[devices]]"));
+        assert!(!serialized.contains("fallback_to_default"));
     }
 }
@@ -0,0 +1,1150 @@
+use anyhow::{Context, Result};
+
+use crate::audio::{self, DeviceInfo, Endpoint, VolumeBackend};
+use crate::config::{ChannelTarget, Config, OutputConfig, Policy, StreamTarget};
+use crate::volume::{Ramp, VolumeLevel};
+
+/// Volume one run sets on one device
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub endpoint: Endpoint,
+    /// Friendly name used in messages
+    pub name: String,
+    pub level: VolumeLevel,
+    /// Mute state to enforce; left alone when `None`
+    pub muted: Option<bool>,
+    /// Channel levels to enforce; left alone when `None`
+    pub channels: Option<ChannelTarget>,
+    /// How far the volume may be from `level`
+    pub policy: Policy,
+    /// Steps to move the volume in; set at once when `None`
+    pub ramp: Option<Ramp>,
+    /// Bounds on what is set, whatever `level` asks for
+    pub limits: Limits,
+}
+
+impl Assignment {
+    /// Level the device is enforced at: `level` within the volume limits
+    pub fn target(&self) -> VolumeLevel {
+        self.limits
+            .cap(self.level)
+            .map_or(self.level, |(level, _)| level)
+    }
+
+    /// Where a complying volume is, completing "volume already ..."
+    pub fn compliance(&self) -> String {
+        let compliance = self.policy.compliance(self.target());
+        match self.limits.cap(self.level) {
+            Ok((_, Some(clamp))) => format!("{} ({})", compliance, clamp),
+            _ => compliance,
+        }
+    }
+
+    /// Settings an interrupted ramp left unset, completing "left alone ..."
+    pub fn skipped(&self) -> &'static str {
+        match (self.channels.is_some(), self.muted.is_some()) {
+            (true, true) => ", channels and mute state not set",
+            (true, false) => ", channels not set",
+            (false, true) => ", mute state not set",
+            (false, false) => "",
+        }
+    }
+}
+
+/// Safety limits on the microphone volume (`min_volume`, `max_volume` and
+/// `max_step_per_run` in the config file)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub min: Option<VolumeLevel>,
+    pub max: Option<VolumeLevel>,
+    pub max_step: Option<VolumeLevel>,
+}
+
+/// A level that was not set as requested because of a limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clamp {
+    pub requested: VolumeLevel,
+    /// Config setting that applied
+    pub limit: &'static str,
+    /// Value of that setting
+    pub value: VolumeLevel,
+}
+
+impl std::fmt::Display for Clamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "clamped from {} by {} {}",
+            self.requested, self.limit, self.value
+        )
+    }
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min: config.min_volume,
+            max: config.max_volume,
+            max_step: config.max_step_per_run,
+        }
+    }
+
+    /// `level` raised to `min` or lowered to `max`, with the clamp that
+    /// applied; fails when a limit is in another unit than `level`, as it
+    /// cannot be checked then
+    pub fn cap(&self, level: VolumeLevel) -> Result<(VolumeLevel, Option<Clamp>)> {
+        for (limit, bound, side) in [
+            ("min_volume", self.min, -1.0),
+            ("max_volume", self.max, 1.0),
+        ] {
+            let Some(bound) = bound else {
+                continue;
+            };
+            if (level.value() - same_unit(level, bound, limit)?) * side > 0.0 {
+                let clamp = Clamp {
+                    requested: level,
+                    limit,
+                    value: bound,
+                };
+                return Ok((bound, Some(clamp)));
+            }
+        }
+        Ok((level, None))
+    }
+
+    /// `level` capped, and moved no further than `max_step` from the current
+    /// volume of `endpoint`
+    pub fn clamp(
+        &self,
+        backend: &dyn VolumeBackend,
+        endpoint: &Endpoint,
+        level: VolumeLevel,
+    ) -> Result<(VolumeLevel, Option<Clamp>)> {
+        let (capped, clamp) = self.cap(level)?;
+        let Some(max_step) = self.max_step else {
+            return Ok((capped, clamp));
+        };
+        let step = same_unit(capped, max_step, "max_step_per_run")?.abs();
+        let (offset, _) = offset(backend, endpoint, capped)?;
+        if !offset.is_finite() {
+            anyhow::bail!(
+                "The volume is at zero, where a max_step_per_run of {} cannot be measured; \
+                 set it in percent",
+                max_step
+            );
+        }
+        if offset.abs() <= step {
+            return Ok((capped, clamp));
+        }
+
+        let current = capped.value() + offset;
+        let stepped = capped.with_value(current - step * offset.signum());
+        let clamp = Clamp {
+            requested: level,
+            limit: "max_step_per_run",
+            value: max_step,
+        };
+        Ok((stepped, Some(clamp)))
+    }
+}
+
+/// Value of `limit`, which must be in the unit of `level` to be compared
+/// with it
+fn same_unit(level: VolumeLevel, limit: VolumeLevel, name: &str) -> Result<f32> {
+    match (level, limit) {
+        (VolumeLevel::Scalar(_), VolumeLevel::Scalar(value))
+        | (VolumeLevel::Db(_), VolumeLevel::Db(value)) => Ok(value),
+        _ => anyhow::bail!(
+            "{} {} cannot be checked against {}, which is in another unit; set both in \
+             percent or both in dB",
+            name,
+            limit,
+            level
+        ),
+    }
+}
+
+/// What enforcing an assignment wrote
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Everything complied, so nothing was written
+    Kept,
+    /// The volume was set to this level, which the policy may have picked
+    /// instead of the target, or a limit clamped it to
+    Set(VolumeLevel, Option<Clamp>),
+    /// The volume complied; only channel levels or the mute state were set
+    Adjusted,
+    /// Someone else changed the volume while it was ramping to this level,
+    /// so it was left where they put it, and channel levels and the mute
+    /// state were not set
+    Interrupted(VolumeLevel),
+}
+
+/// Channels within this distance of the master volume count as balanced
+const BALANCE_TOLERANCE: f32 = 0.005;
+
+/// Smallest distance within which a scalar target counts as set; coarser
+/// controls widen it to their step
+const LEVEL_TOLERANCE: f32 = 0.005;
+
+/// Smallest distance in dB within which a dB target counts as set; coarser
+/// hardware steps widen it to the step
+const DB_TOLERANCE: f32 = 0.1;
+
+/// Assignments for one run over the present `devices`
+///
+/// Every device matched by a `[[devices]]` entry of `config` gets that entry's
+/// target, the first matching entry winning. The controlled microphones,
+/// `primaries`, get `level` unless an entry already covers them. Entries
+/// without a mute state or channel levels of their own enforce the top-level
+/// ones.
+pub fn plan(
+    devices: &[DeviceInfo],
+    config: &Config,
+    primaries: &[Endpoint],
+    level: VolumeLevel,
+) -> Vec<Assignment> {
+    let mut assignments: Vec<Assignment> = Vec::new();
+
+    for target in &config.devices {
+        for device in audio::matching_devices(devices, &target.device) {
+            let endpoint = Endpoint::Id(device.id.clone());
+            if assignments.iter().any(|a| a.endpoint == endpoint) {
+                continue;
+            }
+            assignments.push(Assignment {
+                endpoint,
+                name: device.name.clone(),
+                level: target.target_volume,
+                muted: target.muted.or(config.muted),
+                channels: target.channels.clone().or(config.channels.clone()),
+                policy: config.policy.clone(),
+                ramp: config.ramp,
+                limits: Limits::from_config(config),
+            });
+        }
+    }
+
+    for primary in primaries {
+        let primary_device = devices.iter().find(|device| match primary {
+            Endpoint::Default => device.is_default(),
+            Endpoint::Id(id) => &device.id == id,
+        });
+        let covered = assignments.iter().any(|a| {
+            a.endpoint == *primary
+                || primary_device
+                    .is_some_and(|device| a.endpoint == Endpoint::Id(device.id.clone()))
+        });
+        if !covered {
+            assignments.push(Assignment {
+                endpoint: primary.clone(),
+                name: primary_device.map_or_else(|| primary.to_string(), |d| d.name.clone()),
+                level,
+                muted: config.muted,
+                channels: config.channels.clone(),
+                policy: config.policy.clone(),
+                ramp: config.ramp,
+                limits: Limits::from_config(config),
+            });
+        }
+    }
+
+    assignments
+}
+
+/// Set every assigned volume and mute state that does not comply yet,
+/// carrying on past failing devices
+///
+/// Fails after all assignments were attempted if any of them failed.
+pub fn apply(backend: &dyn VolumeBackend, assignments: &[Assignment], quiet: bool) -> Result<()> {
+    let mut first_error = None;
+    let mut failures = 0;
+
+    for assignment in assignments {
+        match apply_one(backend, assignment) {
+            Ok(Outcome::Interrupted(level)) => {
+                if !quiet {
+                    println!(
+                        "{}: volume changed by someone else while ramping to {}, left alone{}",
+                        assignment.name,
+                        level,
+                        assignment.skipped()
+                    );
+                }
+            }
+            Ok(Outcome::Kept) => {
+                if !quiet {
+                    println!(
+                        "{}: volume already {}",
+                        assignment.name,
+                        assignment.compliance()
+                    );
+                }
+            }
+            Ok(outcome) => {
+                if !quiet {
+                    let mute_note = match assignment.muted {
+                        Some(true) => ", muted",
+                        Some(false) => ", unmuted",
+                        None => "",
+                    };
+                    let channels_note = match &assignment.channels {
+                        Some(channels) => format!(", channels {}", channels),
+                        None => String::new(),
+                    };
+                    let volume_note = match outcome {
+                        Outcome::Set(level, None) => format!("volume set to {}", level),
+                        Outcome::Set(level, Some(clamp)) => {
+                            format!("volume set to {} ({})", level, clamp)
+                        }
+                        _ => format!("volume already {}", assignment.compliance()),
+                    };
+                    println!(
+                        "{}: {}{}{}",
+                        assignment.name, volume_note, mute_note, channels_note
+                    );
+                }
+            }
+            Err(e) => {
+                if !quiet {
+                    eprintln!("{}: {:#}", assignment.name, e);
+                }
+                failures += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e.context(format!(
+            "Failed to set volume on {} of {} devices",
+            failures,
+            assignments.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Enforce one assignment, writing only what does not comply yet
+pub fn apply_one(backend: &dyn VolumeBackend, assignment: &Assignment) -> Result<Outcome> {
+    if is_met(backend, assignment)? {
+        return Ok(Outcome::Kept);
+    }
+
+    let endpoint = &assignment.endpoint;
+    let (target, capped) = assignment.limits.cap(assignment.level)?;
+    let level = match level_to_set(backend, endpoint, target, &assignment.policy)? {
+        Some(level) => {
+            let (level, clamp) = assignment.limits.clamp(backend, endpoint, level)?;
+            if !set_level(backend, endpoint, level, assignment.ramp.as_ref())? {
+                return Ok(Outcome::Interrupted(level));
+            }
+            // Name the target in the message, not the level the policy or
+            // the cap made of it
+            let clamp = clamp.or(capped).map(|clamp| Clamp {
+                requested: assignment.level,
+                ..clamp
+            });
+            Some((level, clamp))
+        }
+        None => None,
+    };
+    if let Some(channels) = &assignment.channels {
+        apply_channels(backend, endpoint, channels)?;
+    }
+    if let Some(muted) = assignment.muted
+        && backend
+            .get_mute(endpoint)
+            .context("Failed to get mute state")?
+            != muted
+    {
+        backend
+            .set_mute(endpoint, muted)
+            .context("Failed to set mute state")?;
+    }
+    Ok(level.map_or(Outcome::Adjusted, |(level, clamp)| {
+        Outcome::Set(level, clamp)
+    }))
+}
+
+/// Set the volume of `endpoint` to `level`, along `ramp` if any; returns
+/// `false` when someone else changed the volume during the ramp
+pub fn set_level(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+    ramp: Option<&Ramp>,
+) -> Result<bool> {
+    match ramp {
+        Some(ramp) => audio::ramp_level(backend, endpoint, level, ramp),
+        None => backend.set_level(endpoint, level).map(|()| true),
+    }
+    .context("Failed to set volume")
+}
+
+/// Distance within which the microphone volume of `endpoint` counts as at a
+/// scalar target, given the resolution of its control
+fn level_tolerance(backend: &dyn VolumeBackend, endpoint: &Endpoint) -> f32 {
+    backend
+        .volume_step(endpoint)
+        .ok()
+        .flatten()
+        .map_or(LEVEL_TOLERANCE, |step| step.max(LEVEL_TOLERANCE))
+}
+
+/// How far the volume of `endpoint` is above `level`, in the unit of
+/// `level`, with the distance within which it counts as at `level`
+fn offset(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+) -> Result<(f32, f32)> {
+    match level {
+        VolumeLevel::Scalar(target) => {
+            let volume = backend
+                .get_volume(endpoint)
+                .context("Failed to get current volume")?;
+            Ok((volume - target, level_tolerance(backend, endpoint)))
+        }
+        VolumeLevel::Db(target) => {
+            let db = backend
+                .get_volume_db(endpoint)
+                .context("Failed to get current volume")?;
+            let tolerance = backend
+                .volume_range(endpoint)
+                .ok()
+                .and_then(|range| range.step_db)
+                .map_or(DB_TOLERANCE, |step| step.max(DB_TOLERANCE));
+            Ok((db - target, tolerance))
+        }
+    }
+}
+
+/// Level to set on `endpoint` for the target `level` under `policy`; `None`
+/// when its volume already complies
+pub fn level_to_set(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+    policy: &Policy,
+) -> Result<Option<VolumeLevel>> {
+    let set_unless = |complies: bool| Ok((!complies).then_some(level));
+    match policy {
+        Policy::Exact { tolerance } => {
+            let (offset, rounding) = offset(backend, endpoint, level)?;
+            let tolerance = match (tolerance, level) {
+                (None, _) => rounding,
+                (Some(VolumeLevel::Scalar(tolerance)), VolumeLevel::Scalar(_))
+                | (Some(VolumeLevel::Db(tolerance)), VolumeLevel::Db(_)) => {
+                    tolerance.abs().max(rounding)
+                }
+                (Some(tolerance), _) => anyhow::bail!(
+                    "The policy tolerance {} is in another unit than the target {}",
+                    tolerance,
+                    level
+                ),
+            };
+            set_unless(offset.abs() <= tolerance)
+        }
+        Policy::Floor => {
+            let (offset, rounding) = offset(backend, endpoint, level)?;
+            set_unless(offset >= -rounding)
+        }
+        Policy::Ceiling => {
+            let (offset, rounding) = offset(backend, endpoint, level)?;
+            set_unless(offset <= rounding)
+        }
+        Policy::Range { min, max } => {
+            let (below, rounding) = offset(backend, endpoint, *min)?;
+            if below < -rounding {
+                return Ok(Some(*min));
+            }
+            let (above, rounding) = offset(backend, endpoint, *max)?;
+            Ok((above > rounding).then_some(*max))
+        }
+    }
+}
+
+/// Whether `endpoint` is at `level`, as far as `policy` goes
+pub fn level_met(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    level: VolumeLevel,
+    policy: &Policy,
+) -> Result<bool> {
+    Ok(level_to_set(backend, endpoint, level, policy)?.is_none())
+}
+
+/// Whether the device of `assignment` already has its volume, channel levels
+/// and mute state
+///
+/// Explicit channel levels decide the master volume, so the level is not
+/// compared then.
+pub fn is_met(backend: &dyn VolumeBackend, assignment: &Assignment) -> Result<bool> {
+    let endpoint = &assignment.endpoint;
+    let levels_met = match &assignment.channels {
+        Some(ChannelTarget::Levels(levels)) => {
+            let channels = backend
+                .get_channel_volumes(endpoint)
+                .context("Failed to get channel volumes")?;
+            let tolerance = level_tolerance(backend, endpoint);
+            channels.len() == levels.len()
+                && channels
+                    .iter()
+                    .zip(levels)
+                    .all(|(channel, level)| (channel.volume - level).abs() <= tolerance)
+        }
+        Some(ChannelTarget::Balanced) => {
+            let master = backend
+                .get_volume(endpoint)
+                .context("Failed to get current volume")?;
+            let channels = backend
+                .get_channel_volumes(endpoint)
+                .context("Failed to get channel volumes")?;
+            level_met(backend, endpoint, assignment.target(), &assignment.policy)?
+                && channels
+                    .iter()
+                    .all(|channel| (channel.volume - master).abs() <= BALANCE_TOLERANCE)
+        }
+        None => level_met(backend, endpoint, assignment.target(), &assignment.policy)?,
+    };
+    if !levels_met {
+        return Ok(false);
+    }
+
+    match assignment.muted {
+        Some(muted) => Ok(backend
+            .get_mute(endpoint)
+            .context("Failed to get mute state")?
+            == muted),
+        None => Ok(true),
+    }
+}
+
+/// Whether every open capture stream matching a `[[streams]]` entry has that
+/// entry's volume
+pub fn streams_met(backend: &dyn VolumeBackend, targets: &[StreamTarget]) -> Result<bool> {
+    let streams = backend
+        .list_streams()
+        .context("Failed to list capture streams")?;
+    Ok(streams.iter().all(|stream| {
+        targets
+            .iter()
+            .find(|target| audio::name_matches(&stream.application, &target.application))
+            .is_none_or(|target| (stream.volume - target.target_volume).abs() <= LEVEL_TOLERANCE)
+    }))
+}
+
+/// Assignment of the `[output]` target to the output device it selects
+pub fn output_assignment(backend: &dyn VolumeBackend, output: &OutputConfig) -> Result<Assignment> {
+    let endpoint = match &output.device {
+        Some(selector) => {
+            let devices = backend
+                .list_devices()
+                .context("Failed to list output devices")?;
+            let device = audio::find_device(&devices, selector)?.with_context(|| {
+                format!(
+                    "No present output device matches '{}' (see `devices --output`)",
+                    selector
+                )
+            })?;
+            Endpoint::Id(device.id)
+        }
+        None => Endpoint::Default,
+    };
+
+    Ok(Assignment {
+        endpoint,
+        name: "Output".to_string(),
+        level: output.target_volume,
+        muted: output.muted,
+        channels: None,
+        policy: Policy::default(),
+        // `[ramp]` only applies to the microphones
+        ramp: None,
+        limits: Limits::default(),
+    })
+}
+
+/// Enforce the `[output]` target on the output device it selects, through a
+/// backend for output devices
+pub fn apply_output(backend: &dyn VolumeBackend, output: &OutputConfig, quiet: bool) -> Result<()> {
+    let assignment = output_assignment(backend, output)?;
+    let outcome = apply_one(backend, &assignment).context("Failed to set output volume")?;
+
+    if !quiet {
+        match outcome {
+            Outcome::Set(level, _) => println!("Output volume set to: {}", level),
+            // Not ramped, so never interrupted
+            _ => println!("Output volume already at {}", output.target_volume),
+        }
+        match output.muted {
+            Some(true) => println!("Output kept muted"),
+            Some(false) => println!("Output kept unmuted"),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Set every open capture stream to the volume of the first `[[streams]]`
+/// entry its application matches
+///
+/// Like `apply`, a stream that fails does not stop the others.
+pub fn apply_streams(
+    backend: &dyn VolumeBackend,
+    targets: &[StreamTarget],
+    quiet: bool,
+) -> Result<()> {
+    let streams = backend
+        .list_streams()
+        .context("Failed to list capture streams")?;
+    let mut first_error = None;
+    let mut failures = 0;
+
+    for stream in &streams {
+        let Some(target) = targets
+            .iter()
+            .find(|target| audio::name_matches(&stream.application, &target.application))
+        else {
+            continue;
+        };
+        if (stream.volume - target.target_volume).abs() <= LEVEL_TOLERANCE {
+            if !quiet {
+                println!(
+                    "{}: stream volume already {}",
+                    stream.application,
+                    VolumeLevel::Scalar(target.target_volume)
+                );
+            }
+            continue;
+        }
+
+        match backend.set_stream_volume(&stream.id, target.target_volume) {
+            Ok(()) => {
+                if !quiet {
+                    println!(
+                        "{}: stream volume set to {}",
+                        stream.application,
+                        VolumeLevel::Scalar(target.target_volume)
+                    );
+                }
+            }
+            Err(e) => {
+                if !quiet {
+                    eprintln!("{}: {:#}", stream.application, e);
+                }
+                failures += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e.context(format!(
+            "Failed to set the volume of {} application streams",
+            failures
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Set the channel volumes of `endpoint` to `target`, after its master
+/// volume was set
+///
+/// Balancing raises or lowers every channel to the master volume, and leaves
+/// channels that are already there untouched.
+pub fn apply_channels(
+    backend: &dyn VolumeBackend,
+    endpoint: &Endpoint,
+    target: &ChannelTarget,
+) -> Result<()> {
+    let volumes = match target {
+        ChannelTarget::Balanced => {
+            let master = backend
+                .get_volume(endpoint)
+                .context("Failed to get current volume")?;
+            let channels = backend
+                .get_channel_volumes(endpoint)
+                .context("Failed to get channel volumes")?;
+            if channels
+                .iter()
+                .all(|channel| (channel.volume - master).abs() <= BALANCE_TOLERANCE)
+            {
+                return Ok(());
+            }
+            vec![master; channels.len()]
+        }
+        ChannelTarget::Levels(levels) => levels.clone(),
+    };
+
+    backend
+        .set_channel_volumes(endpoint, &volumes)
+        .context("Failed to set channel volumes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{DeviceState, MockBackend, Operation, Role};
+    use crate::config::DeviceTarget;
+
+    fn device(id: &str, name: &str, default: bool) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            state: DeviceState::Active,
+            volume: None,
+            muted: None,
+            default_for: if default {
+                Role::ALL.to_vec()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    fn target(device: &str, target_volume: f32) -> DeviceTarget {
+        DeviceTarget {
+            device: device.to_string(),
+            target_volume: VolumeLevel::Scalar(target_volume),
+            muted: None,
+            channels: None,
+        }
+    }
+
+    fn volumes(assignments: &[Assignment]) -> Vec<(&str, VolumeLevel)> {
+        assignments
+            .iter()
+            .map(|a| (a.name.as_str(), a.level))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_without_targets() {
+        let devices = [device("a", "Array", true)];
+        let config = Config::default();
+        let assignments = plan(
+            &devices,
+            &config,
+            &[Endpoint::Default],
+            VolumeLevel::Scalar(0.9),
+        );
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].endpoint, Endpoint::Default);
+        assert_eq!(assignments[0].name, "Array");
+
+        let assignments = plan(&[], &config, &[Endpoint::Default], VolumeLevel::Scalar(0.9));
+        assert_eq!(assignments[0].name, "default device");
+    }
+
+    #[test]
+    fn test_plan_with_targets() {
+        let devices = [
+            device("a", "Microphone Array", true),
+            device("b", "Headset Microphone", false),
+            device("c", "Webcam", false),
+        ];
+        let mut config = Config {
+            devices: vec![
+                target("headset", 0.85),
+                target("*Microphone*", 0.7),
+                target("missing", 0.1),
+            ],
+            ..Config::default()
+        };
+
+        let assignments = plan(
+            &devices,
+            &config,
+            &[Endpoint::Default],
+            VolumeLevel::Scalar(0.9),
+        );
+        assert_eq!(
+            volumes(&assignments),
+            [
+                ("Headset Microphone", VolumeLevel::Scalar(0.85)),
+                ("Microphone Array", VolumeLevel::Scalar(0.7))
+            ]
+        );
+
+        let webcam = Endpoint::Id("c".to_string());
+        let headset = Endpoint::Id("b".to_string());
+        let assignments = plan(
+            &devices,
+            &Config::default(),
+            &[webcam.clone(), headset, webcam.clone()],
+            VolumeLevel::Scalar(0.9),
+        );
+        assert_eq!(
+            volumes(&assignments),
+            [
+                ("Webcam", VolumeLevel::Scalar(0.9)),
+                ("Headset Microphone", VolumeLevel::Scalar(0.9))
+            ]
+        );
+
+        config.devices.truncate(1);
+        let assignments = plan(&devices, &config, &[webcam], VolumeLevel::Db(-6.0));
+        assert_eq!(
+            volumes(&assignments),
+            [
+                ("Headset Microphone", VolumeLevel::Scalar(0.85)),
+                ("Webcam", VolumeLevel::Db(-6.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_mute_states() {
+        let devices = [
+            device("a", "Microphone Array", true),
+            device("b", "Headset", false),
+            device("c", "Webcam", false),
+        ];
+        let config = Config {
+            muted: Some(false),
+            channels: Some(ChannelTarget::Balanced),
+            devices: vec![
+                DeviceTarget {
+                    muted: Some(true),
+                    channels: Some(ChannelTarget::Levels(vec![0.5, 0.4])),
+                    ..target("Webcam", 0.5)
+                },
+                target("Headset", 0.85),
+            ],
+            ..Config::default()
+        };
+
+        let assignments = plan(
+            &devices,
+            &config,
+            &[Endpoint::Default],
+            VolumeLevel::Scalar(0.9),
+        );
+        let mute_states: Vec<(&str, Option<bool>)> = assignments
+            .iter()
+            .map(|a| (a.name.as_str(), a.muted))
+            .collect();
+        assert_eq!(
+            mute_states,
+            [
+                ("Webcam", Some(true)),
+                ("Headset", Some(false)),
+                ("Microphone Array", Some(false))
+            ]
+        );
+        assert_eq!(
+            assignments[0].channels,
+            Some(ChannelTarget::Levels(vec![0.5, 0.4]))
+        );
+        assert_eq!(assignments[1].channels, Some(ChannelTarget::Balanced));
+        assert_eq!(assignments[2].channels, Some(ChannelTarget::Balanced));
+    }
+
+    #[test]
+    fn test_apply_channels() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.8, false)
+            .with_channels(&[0.8, 0.5]);
+        let channel_volumes = || -> Vec<f32> { backend.devices()[0].channels.clone() };
+
+        apply_channels(&backend, &Endpoint::Default, &ChannelTarget::Balanced).unwrap();
+        assert_eq!(channel_volumes(), [0.8, 0.8]);
+
+        let levels = ChannelTarget::Levels(vec![0.6, 0.7]);
+        apply_channels(&backend, &Endpoint::Default, &levels).unwrap();
+        assert_eq!(channel_volumes(), [0.6, 0.7]);
+
+        let mono = ChannelTarget::Levels(vec![0.6]);
+        let err = apply_channels(&backend, &Endpoint::Default, &mono).unwrap_err();
+        assert!(format!("{:#}", err).contains("2 channels but 1 channel levels"));
+
+        // Already balanced channels are not written
+        let failing = MockBackend::new()
+            .with_device("Mic", 0.7, false)
+            .with_failure(Operation::SetVolume);
+        apply_channels(&failing, &Endpoint::Default, &ChannelTarget::Balanced).unwrap();
+        assert!(apply_channels(&failing, &Endpoint::Default, &levels).is_err());
+    }
+
+    #[test]
+    fn test_apply_continues_past_failures() {
+        let backend = MockBackend::new().with_device("Mic", 0.3, true);
+        let assignments = [
+            Assignment {
+                endpoint: Endpoint::Id("unknown".to_string()),
+                name: "Unknown".to_string(),
+                level: VolumeLevel::Scalar(0.5),
+                muted: None,
+                channels: None,
+                policy: Policy::default(),
+                ramp: None,
+                limits: Limits::default(),
+            },
+            Assignment {
+                endpoint: Endpoint::Default,
+                name: "Mic".to_string(),
+                level: VolumeLevel::Db(-12.0),
+                muted: Some(false),
+                channels: Some(ChannelTarget::Balanced),
+                policy: Policy::default(),
+                ramp: None,
+                limits: Limits::default(),
+            },
+        ];
+
+        let err = apply(&backend, &assignments, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("1 of 2 devices"));
+        assert_eq!(backend.devices()[0].volume, 0.8);
+        assert!(!backend.devices()[0].muted);
+
+        backend.set_mute(&Endpoint::Default, true).unwrap();
+        let failing = backend.with_failure(Operation::SetMute);
+        assert!(apply(&failing, &assignments[1..], true).is_err());
+
+        // What already complies is not written
+        let compliant = Assignment {
+            muted: Some(true),
+            ..assignments[1].clone()
+        };
+        apply(&failing, &[compliant], true).unwrap();
+
+        // Nor is a mute state that complies when the volume does not
+        let muted = Assignment {
+            level: VolumeLevel::Scalar(0.5),
+            muted: Some(true),
+            channels: None,
+            ..assignments[1].clone()
+        };
+        assert!(matches!(
+            apply_one(&failing, &muted).unwrap(),
+            Outcome::Set(VolumeLevel::Scalar(0.5), None)
+        ));
+        assert!(failing.devices()[0].muted);
+    }
+
+    #[test]
+    fn test_is_met() {
+        let backend = MockBackend::from_spec("Mic=80,channels=80/50").unwrap();
+        let mut assignment = Assignment {
+            endpoint: Endpoint::Default,
+            name: "Mic".to_string(),
+            level: VolumeLevel::Scalar(0.8),
+            muted: Some(false),
+            channels: None,
+            policy: Policy::default(),
+            ramp: None,
+            limits: Limits::default(),
+        };
+        assert!(is_met(&backend, &assignment).unwrap());
+
+        assignment.level = VolumeLevel::Db(-12.0);
+        assert!(is_met(&backend, &assignment).unwrap());
+        assignment.level = VolumeLevel::Db(-13.0);
+        assert!(!is_met(&backend, &assignment).unwrap());
+
+        assignment.level = VolumeLevel::Scalar(0.8);
+        assignment.channels = Some(ChannelTarget::Balanced);
+        assert!(!is_met(&backend, &assignment).unwrap());
+        assignment.channels = Some(ChannelTarget::Levels(vec![0.8, 0.5]));
+        assert!(is_met(&backend, &assignment).unwrap());
+
+        backend.set_mute(&Endpoint::Default, true).unwrap();
+        assert!(!is_met(&backend, &assignment).unwrap());
+
+        let backend = backend.with_stream("Firefox", 0.3);
+        let targets = [StreamTarget {
+            application: "Firefox".to_string(),
+            target_volume: 0.3,
+        }];
+        assert!(streams_met(&backend, &targets).unwrap());
+        backend.set_stream_volume("stream-0", 0.9).unwrap();
+        assert!(!streams_met(&backend, &targets).unwrap());
+        assert!(streams_met(&backend, &[]).unwrap());
+
+        // A coarse control cannot reach 95% exactly, and counts as there
+        let backend = MockBackend::from_spec("Mic=50,steps=31").unwrap();
+        let assignment = Assignment {
+            level: VolumeLevel::Scalar(0.95),
+            muted: None,
+            channels: None,
+            ..assignment
+        };
+        apply(&backend, std::slice::from_ref(&assignment), true).unwrap();
+        let volume = backend.get_volume(&Endpoint::Default).unwrap();
+        assert!((volume - 0.95).abs() > LEVEL_TOLERANCE);
+        assert!(is_met(&backend, &assignment).unwrap());
+    }
+
+    #[test]
+    fn test_level_to_set() {
+        let backend = MockBackend::new().with_device("Mic", 0.6, false);
+        let level = |target: f32, policy: Policy| {
+            level_to_set(
+                &backend,
+                &Endpoint::Default,
+                VolumeLevel::Scalar(target),
+                &policy,
+            )
+            .unwrap()
+        };
+        let exact = |tolerance: Option<f32>| Policy::Exact {
+            tolerance: tolerance.map(VolumeLevel::Scalar),
+        };
+
+        assert_eq!(level(0.6, exact(None)), None);
+        assert_eq!(level(0.65, exact(None)), Some(VolumeLevel::Scalar(0.65)));
+        assert_eq!(level(0.65, exact(Some(0.05))), None);
+        assert_eq!(
+            level(0.7, exact(Some(0.05))),
+            Some(VolumeLevel::Scalar(0.7))
+        );
+
+        assert_eq!(level(0.5, Policy::Floor), None);
+        assert_eq!(level(0.8, Policy::Floor), Some(VolumeLevel::Scalar(0.8)));
+        assert_eq!(level(0.8, Policy::Ceiling), None);
+        assert_eq!(level(0.5, Policy::Ceiling), Some(VolumeLevel::Scalar(0.5)));
+
+        let range = |min, max| Policy::Range {
+            min: VolumeLevel::Scalar(min),
+            max: VolumeLevel::Scalar(max),
+        };
+        assert_eq!(level(0.9, range(0.5, 0.7)), None);
+        assert_eq!(level(0.9, range(0.7, 0.9)), Some(VolumeLevel::Scalar(0.7)));
+        assert_eq!(level(0.9, range(0.2, 0.4)), Some(VolumeLevel::Scalar(0.4)));
+
+        // A dB tolerance needs a dB target
+        let db_tolerance = Policy::Exact {
+            tolerance: Some(VolumeLevel::Db(1.0)),
+        };
+        assert!(
+            level_to_set(
+                &backend,
+                &Endpoint::Default,
+                VolumeLevel::Scalar(0.6),
+                &db_tolerance
+            )
+            .is_err()
+        );
+        assert_eq!(
+            level_to_set(
+                &backend,
+                &Endpoint::Default,
+                VolumeLevel::Db(-24.5),
+                &db_tolerance
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            min: Some(VolumeLevel::Scalar(0.2)),
+            max: Some(VolumeLevel::Scalar(0.9)),
+            max_step: Some(VolumeLevel::Scalar(0.1)),
+        };
+        let (level, clamp) = limits.cap(VolumeLevel::Scalar(1.0)).unwrap();
+        assert_eq!(level, VolumeLevel::Scalar(0.9));
+        assert_eq!(
+            clamp.unwrap().to_string(),
+            "clamped from 100% by max_volume 90%"
+        );
+        assert_eq!(
+            limits.cap(VolumeLevel::Scalar(0.1)).unwrap().0,
+            VolumeLevel::Scalar(0.2)
+        );
+        assert_eq!(
+            limits.cap(VolumeLevel::Scalar(0.5)).unwrap(),
+            (VolumeLevel::Scalar(0.5), None)
+        );
+        let err = limits.cap(VolumeLevel::Db(-6.0)).unwrap_err();
+        assert!(format!("{:#}", err).contains("another unit"));
+
+        // The step is taken from the current volume
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let clamp = |level| limits.clamp(&backend, &Endpoint::Default, level).unwrap();
+        let (level, note) = clamp(VolumeLevel::Scalar(1.0));
+        assert!((level.value() - 0.6).abs() < 1e-6);
+        assert_eq!(note.unwrap().limit, "max_step_per_run");
+        assert!((clamp(VolumeLevel::Scalar(0.1)).0.value() - 0.4).abs() < 1e-6);
+        assert_eq!(
+            clamp(VolumeLevel::Scalar(0.55)),
+            (VolumeLevel::Scalar(0.55), None)
+        );
+
+        let db_step = Limits {
+            max_step: Some(VolumeLevel::Db(3.0)),
+            ..Limits::default()
+        };
+        let (level, _) = db_step
+            .clamp(&backend, &Endpoint::Default, VolumeLevel::Db(-6.0))
+            .unwrap();
+        assert!((level.value() + 27.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_apply_streams() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.5, false)
+            .with_stream("Firefox", 0.3)
+            .with_stream("Zoom Meetings", 0.4)
+            .with_stream("OBS", 0.5);
+        let targets = [
+            StreamTarget {
+                application: "firefox".to_string(),
+                target_volume: 0.9,
+            },
+            StreamTarget {
+                application: "Zoom*".to_string(),
+                target_volume: 1.0,
+            },
+            StreamTarget {
+                application: "*".to_string(),
+                target_volume: 0.2,
+            },
+        ];
+
+        apply_streams(&backend, &targets[..2], true).unwrap();
+        let volumes: Vec<f32> = backend.streams().iter().map(|s| s.volume).collect();
+        assert_eq!(volumes, [0.9, 1.0, 0.5]);
+
+        // The first matching entry wins
+        apply_streams(&backend, &targets, true).unwrap();
+        let volumes: Vec<f32> = backend.streams().iter().map(|s| s.volume).collect();
+        assert_eq!(volumes, [0.9, 1.0, 0.2]);
+
+        // Streams at their target are not written
+        backend.set_stream_volume("stream-2", 0.5).unwrap();
+        let failing = backend.with_failure(Operation::SetVolume);
+        let err = apply_streams(&failing, &targets, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("1 application streams"));
+    }
+
+    #[test]
+    fn test_apply_output() {
+        let backend = MockBackend::new()
+            .with_device("Speakers", 0.3, true)
+            .with_device("USB Headphones", 0.9, false);
+        let mut output = OutputConfig {
+            target_volume: VolumeLevel::Scalar(0.6),
+            muted: Some(false),
+            device: None,
+        };
+        apply_output(&backend, &output, true).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.6);
+        assert!(!backend.devices()[0].muted);
+        assert_eq!(backend.devices()[1].volume, 0.9);
+
+        output.device = Some("USB*".to_string());
+        output.muted = None;
+        apply_output(&backend, &output, true).unwrap();
+        assert_eq!(backend.devices()[1].volume, 0.6);
+
+        output.device = Some("HDMI".to_string());
+        let err = apply_output(&backend, &output, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("No present output device matches 'HDMI'"));
+    }
+}
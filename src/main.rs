@@ -1,15 +1,37 @@
 mod audio;
 mod config;
+mod enforce;
 mod scheduler;
+mod volume;
+mod watch;
+
+use std::cell::OnceCell;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
+#[cfg(test)]
+use audio::MockBackend;
+use audio::{
+    DeviceInfo, Direction, DryRunBackend, Endpoint, StreamInfo, VolumeBackend, VolumeEvent,
+};
 use clap::Parser;
-use config::{Cli, Commands, Config};
+use config::{BackendKind, Cli, Commands, Config, DeviceRole, StreamTarget};
+use enforce::{Limits, Outcome};
+use scheduler::{MockScheduler, Scheduler};
+use volume::{VolumeChange, VolumeLevel};
+use watch::{Check, DeviceChanges, ECHO_RECHECK, EchoFilter, FightDetector, Response, Watcher};
+#[cfg(windows)]
 use windows::Win32::System::Com::*;
 
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("mic-volume-control supports only Windows and Linux");
+
 /// RAII guard for COM initialization/uninitialization
+#[cfg(windows)]
 struct ComGuard;
 
+#[cfg(windows)]
 impl ComGuard {
     fn new() -> Result<Self> {
         unsafe {
@@ -21,6 +43,7 @@ impl ComGuard {
     }
 }
 
+#[cfg(windows)]
 impl Drop for ComGuard {
     fn drop(&mut self) {
         unsafe {
@@ -29,6 +52,114 @@ impl Drop for ComGuard {
     }
 }
 
+/// Audio backends, scheduler and config location a command runs against
+struct Services {
+    /// Backend for capture devices, created on first use
+    backend: OnceCell<Box<dyn VolumeBackend>>,
+    /// `--backend` override of the configured backend kind, used for the
+    /// output backend too
+    backend_kind: Option<BackendKind>,
+    /// Backend for output devices, created on first use
+    output_backend: OnceCell<Box<dyn VolumeBackend>>,
+    /// In-memory scheduler used instead of the platform one with
+    /// `--backend mock`
+    mock_scheduler: Option<MockScheduler>,
+    /// Config file location, when not the per-user default
+    config_path: Option<PathBuf>,
+    /// `--role` override of the configured role
+    role: Option<DeviceRole>,
+    /// `--dry-run`: the backends report writes instead of making them, and
+    /// no file or task is changed
+    dry_run: bool,
+    /// `--quiet`, which silences the dry-run reports of the backends too
+    quiet: bool,
+}
+
+impl Services {
+    /// Services for the per-user config, with `backend` and `role` overriding
+    /// the configured ones
+    ///
+    /// Nothing is read yet: commands that need no config file, such as
+    /// `uninstall`, work even when it is broken.
+    fn new(
+        backend: Option<BackendKind>,
+        role: Option<DeviceRole>,
+        dry_run: bool,
+        quiet: bool,
+    ) -> Self {
+        Self {
+            backend: OnceCell::new(),
+            backend_kind: backend,
+            output_backend: OnceCell::new(),
+            mock_scheduler: (backend == Some(BackendKind::Mock)).then(MockScheduler::new),
+            config_path: None,
+            role,
+            dry_run,
+            quiet,
+        }
+    }
+
+    /// `backend`, wrapped to only report writes in a dry run
+    fn guard(&self, backend: Box<dyn VolumeBackend>) -> Box<dyn VolumeBackend> {
+        if self.dry_run {
+            Box::new(DryRunBackend::new(backend, self.quiet))
+        } else {
+            backend
+        }
+    }
+
+    fn backend(&self) -> Result<&dyn VolumeBackend> {
+        self.create_backend(&self.backend, Direction::Capture)
+    }
+
+    fn output_backend(&self) -> Result<&dyn VolumeBackend> {
+        self.create_backend(&self.output_backend, Direction::Render)
+    }
+
+    /// Backend for `direction` in `cell`, created from the config file on
+    /// first use
+    fn create_backend<'a>(
+        &self,
+        cell: &'a OnceCell<Box<dyn VolumeBackend>>,
+        direction: Direction,
+    ) -> Result<&'a dyn VolumeBackend> {
+        if let Some(backend) = cell.get() {
+            return Ok(backend.as_ref());
+        }
+        let config = self.load_config()?;
+        let kind = self.backend_kind.unwrap_or(config.backend);
+        let backend = self.guard(audio::create_backend(kind, &config, direction)?);
+        Ok(cell.get_or_init(|| backend).as_ref())
+    }
+
+    fn scheduler(&self) -> Result<Box<dyn Scheduler>> {
+        match &self.mock_scheduler {
+            Some(scheduler) => Ok(Box::new(scheduler.clone())),
+            None => scheduler::default_scheduler(),
+        }
+    }
+
+    fn config_path(&self) -> Result<PathBuf> {
+        match &self.config_path {
+            Some(path) => Ok(path.clone()),
+            None => Config::get_config_path(),
+        }
+    }
+
+    /// Config file contents with the command-line overrides applied
+    fn load_config(&self) -> Result<Config> {
+        let mut config = Config::load(&self.config_path()?)?;
+        if let Some(role) = self.role {
+            config.role = role;
+        }
+        // A ramp checks every step took effect, which none does in a dry run
+        if self.dry_run {
+            config.ramp = None;
+        }
+        Ok(config)
+    }
+}
+
 fn main() {
     if let Err(e) = run() {
         eprintln!("Error: {:#}", e);
@@ -37,88 +168,362 @@ fn main() {
 }
 
 fn run() -> Result<()> {
+    #[cfg(windows)]
     let _com = ComGuard::new()?;
     let cli = Cli::parse();
-    let quiet = cli.quiet;
 
     // If no command provided, clap will show help due to arg_required_else_help
     let Some(command) = cli.command else {
         return Ok(());
     };
 
+    // In a dry run the backends report each change instead, and the usual
+    // messages would claim changes that were not made
+    let dry_run = cli.dry_run && !command.is_read_only();
+    if dry_run && !cli.quiet {
+        println!("Dry run: nothing will be changed");
+    }
+    let quiet = cli.quiet || dry_run;
+
+    let services = Services::new(cli.backend, cli.role, dry_run, cli.quiet);
+    execute(command, quiet, &services)
+}
+
+fn execute(command: Commands, quiet: bool, services: &Services) -> Result<()> {
     match command {
-        Commands::Volume { level } => {
-            if let Some(volume) = level {
+        Commands::Volume { level, db, device } => {
+            let config = services.load_config()?;
+            let backend = services.backend()?;
+            let endpoints = resolve_endpoints(backend, &config, device.as_deref(), quiet)?;
+            let change = match (level, db) {
+                (Some(change), _) => Some(change),
+                (None, Some(db)) => Some(VolumeChange::To(VolumeLevel::Db(db))),
+                (None, None) => None,
+            };
+            // Name the device in messages when the role selects several
+            let label = |endpoint: &Endpoint| match endpoints.len() {
+                1 => String::new(),
+                _ => format!("{}: ", endpoint),
+            };
+
+            if let Some(VolumeChange::To(target)) = change {
                 // Set volume
-                let volume_f32 = volume as f32 / 100.0;
-                audio::AudioController::set_volume(volume_f32).context("Failed to set volume")?;
-                if !quiet {
-                    println!("Microphone volume set to: {}%", volume);
+                enforce_targets(
+                    services,
+                    &config,
+                    &endpoints,
+                    device.is_none(),
+                    target,
+                    quiet,
+                )?;
+            } else if let Some(step) = change {
+                // Step the current volume of the controlled microphones only
+                let limits = Limits::from_config(&config);
+                for endpoint in &endpoints {
+                    let requested = backend
+                        .resolve_change(endpoint, step)
+                        .context("Failed to get current volume")?;
+                    let (target, clamp) = limits.clamp(backend, endpoint, requested)?;
+                    let set = enforce::set_level(backend, endpoint, target, config.ramp.as_ref())?;
+                    if quiet {
+                        continue;
+                    }
+                    if !set {
+                        println!(
+                            "{}Microphone volume changed by someone else while ramping to {}, \
+                             left alone",
+                            label(endpoint),
+                            target
+                        );
+                    } else if let Some(clamp) = clamp {
+                        println!(
+                            "{}Microphone volume set to: {} ({})",
+                            label(endpoint),
+                            target,
+                            clamp
+                        );
+                    } else {
+                        println!("{}Microphone volume set to: {}", label(endpoint), target);
+                    }
                 }
             } else {
                 // Get volume
-                let volume = audio::AudioController::get_current_volume()
-                    .context("Failed to get current volume")?;
-                if !quiet {
-                    println!("Current microphone volume: {:.0}%", volume * 100.0);
+                for endpoint in &endpoints {
+                    let volume = backend
+                        .get_volume(endpoint)
+                        .context("Failed to get current volume")?;
+                    if quiet {
+                        continue;
+                    }
+                    let prefix = label(endpoint);
+                    match backend.get_volume_db(endpoint) {
+                        Ok(db) => println!(
+                            "{}Current microphone volume: {:.0}% ({:.2} dB)",
+                            prefix,
+                            volume * 100.0,
+                            db
+                        ),
+                        Err(_) => println!(
+                            "{}Current microphone volume: {:.0}%",
+                            prefix,
+                            volume * 100.0
+                        ),
+                    }
+                    if let Ok(channels) = backend.get_channel_volumes(endpoint)
+                        && channels.len() > 1
+                    {
+                        let channels: Vec<String> =
+                            channels.iter().map(ToString::to_string).collect();
+                        println!("{}Channels: {}", prefix, channels.join(", "));
+                    }
+                    if let Ok(range) = backend.volume_range(endpoint) {
+                        println!("{}Volume range: {}", prefix, range);
+                    }
                 }
             }
         }
 
-        Commands::Install { volume, interval } => {
-            println!("Installing Windows Task Scheduler task...");
-            println!("  Target volume: {}%", volume);
+        Commands::Install {
+            volume,
+            db,
+            interval,
+            device,
+        } => {
+            let scheduler = services
+                .scheduler()
+                .context("Failed to create task scheduler")?;
+
+            // Save config, keeping settings not covered by the command line
+            let config_path = services.config_path()?;
+            let mut config = services.load_config()?;
+            // The level replaces a dB target saved by an older version
+            config.target_volume = db.map_or(volume, VolumeLevel::Db);
+            config.target_db = None;
+            config.run_interval_minutes = interval;
+            if device.is_some() {
+                config.device = device;
+            }
+            let target = config.target();
+
+            println!("Installing {} task...", scheduler.name());
+            println!("  Target volume: {}", target);
             println!("  Run interval: {} minutes", interval);
+            match &config.device {
+                Some(device) => println!("  Device: {}", device),
+                None => println!("  Role: {}", config.role),
+            }
+            if let Some(output) = &config.output {
+                println!("  Output volume: {}", output.target_volume);
+            }
 
-            let volume_f32 = volume as f32 / 100.0;
+            if services.dry_run {
+                let plan = scheduler
+                    .plan_task(target, interval)
+                    .context("Failed to plan task")?;
+                println!("\nInstall would:");
+                println!("  - Write the config file: {}", config_path.display());
+                for (label, path) in scheduler.installed_files()? {
+                    println!("  - Write {}: {}", label, path.display());
+                }
+                println!(
+                    "  - Register task '{}' running: {}",
+                    scheduler.task_name(),
+                    plan.command
+                );
+                for trigger in &plan.triggers {
+                    println!("      {}", trigger);
+                }
+                return Ok(());
+            }
 
-            // Save config
-            let config = Config {
-                target_volume: volume_f32,
-                run_interval_minutes: interval,
-            };
-            config.save().context("Failed to save configuration")?;
+            config
+                .save_to(&config_path)
+                .context("Failed to save configuration")?;
 
             // Register task
-            let scheduler =
-                scheduler::TaskScheduler::new().context("Failed to create task scheduler")?;
             scheduler
-                .register_task(volume_f32, interval)
+                .register_task(target, interval)
                 .context("Failed to register task")?;
 
             println!("\nTask installed successfully!");
             println!("The task will:");
             println!("  - Run at login (after 1 minute delay)");
             println!("  - Repeat every {} minutes", interval);
-            println!("  - Set microphone volume to {}%", volume);
-            println!("\nYou can manage the task in Windows Task Scheduler.");
+            println!("  - Set microphone volume to {}", target);
+            println!("\nYou can manage the task in {}.", scheduler.name());
         }
 
         Commands::Uninstall => {
-            println!("Uninstalling Windows Task Scheduler task...");
+            let scheduler = services
+                .scheduler()
+                .context("Failed to create task scheduler")?;
 
-            let scheduler =
-                scheduler::TaskScheduler::new().context("Failed to create task scheduler")?;
+            println!("Uninstalling {} task...", scheduler.name());
 
             if !scheduler.is_registered() {
                 println!("Task is not installed.");
                 return Ok(());
             }
 
+            if services.dry_run {
+                println!("Uninstall would:");
+                println!("  - Unregister task '{}'", scheduler.task_name());
+                for (label, path) in scheduler.installed_files()? {
+                    if path.exists() {
+                        println!("  - Delete {}: {}", label, path.display());
+                    }
+                }
+                return Ok(());
+            }
+
             scheduler
                 .unregister_task()
                 .context("Failed to unregister task")?;
 
             println!("Task uninstalled successfully!");
-            println!("VBScript wrapper removed.");
+            println!("Helper files removed.");
+        }
+
+        Commands::Watch => {
+            let config = services.load_config()?;
+            let mut backends = vec![services.backend()?];
+            if config.output.is_some() {
+                backends.push(services.output_backend()?);
+            }
+            let mut watcher = Watcher::new(&backends)?;
+            let mut echoes = EchoFilter::new(ECHO_RECHECK);
+
+            if !quiet {
+                println!("Watching for volume changes (Ctrl+C to stop)...");
+            }
+            restore_targets(services, quiet)?;
+            echoes.settled(current_settings(services, true).ok());
+
+            // Changes that raise no notification, e.g. of streams opened
+            // since, are caught up with on every run interval
+            let resync = Duration::from_secs(u64::from(config.run_interval_minutes.max(1)) * 60);
+            loop {
+                watch_step(services, &mut watcher, &mut echoes, resync, quiet)?;
+            }
+        }
+
+        Commands::Monitor => {
+            let backend = services.backend()?;
+            if !backend.capabilities().device_list {
+                anyhow::bail!(
+                    "The {} backend cannot list devices, which monitoring needs",
+                    backend.name()
+                );
+            }
+            let watcher = Watcher::new(&[backend])?;
+            let mut devices = backend
+                .list_devices()
+                .context("Failed to list capture devices")?;
+
+            if !quiet {
+                println!("Monitoring capture devices (Ctrl+C to stop)...");
+            }
+            loop {
+                monitor_step(services, &watcher, &mut devices, Duration::MAX, quiet)?;
+            }
+        }
+
+        Commands::Lock {
+            max_changes,
+            window,
+            backoff,
+            max_backoff,
+        } => {
+            let config = services.load_config()?;
+            let mut watcher = Watcher::new(&[services.backend()?])?;
+            let mut echoes = EchoFilter::new(ECHO_RECHECK);
+            let mut detector = FightDetector::new(
+                max_changes as usize,
+                Duration::from_secs(window),
+                Duration::from_secs(backoff),
+                Duration::from_secs(max_backoff.max(backoff)),
+            );
+
+            if !quiet {
+                println!("Locking the microphone volume (Ctrl+C to stop)...");
+            }
+            hold_microphone(services, quiet)?;
+            echoes.settled(current_settings(services, false).ok());
+
+            let resync = Duration::from_secs(u64::from(config.run_interval_minutes.max(1)) * 60);
+            loop {
+                lock_step(
+                    services,
+                    &mut watcher,
+                    &mut echoes,
+                    &mut detector,
+                    resync,
+                    quiet,
+                )?;
+            }
+        }
+
+        Commands::Mute { device } => change_mute(services, device.as_deref(), Some(true), quiet)?,
+
+        Commands::Unmute { device } => {
+            change_mute(services, device.as_deref(), Some(false), quiet)?
+        }
+
+        Commands::ToggleMute { device } => change_mute(services, device.as_deref(), None, quiet)?,
+
+        Commands::Streams => {
+            let config = services.load_config()?;
+            let streams = services
+                .backend()?
+                .list_streams()
+                .context("Failed to list capture streams")?;
+
+            if quiet {
+                return Ok(());
+            }
+
+            if streams.is_empty() {
+                println!("No capture streams found.");
+            }
+            for stream in &streams {
+                let target = config
+                    .streams
+                    .iter()
+                    .find(|target| audio::name_matches(&stream.application, &target.application));
+                print_stream(stream, target);
+            }
+        }
+
+        Commands::Devices { output } => {
+            let (backend, direction) = if output {
+                (services.output_backend()?, Direction::Render)
+            } else {
+                (services.backend()?, Direction::Capture)
+            };
+            let devices = backend
+                .list_devices()
+                .with_context(|| format!("Failed to list {} devices", direction))?;
+
+            if quiet {
+                return Ok(());
+            }
+
+            if devices.is_empty() {
+                println!("No {} devices found.", direction);
+            }
+            for device in &devices {
+                print_device(device);
+            }
         }
 
         Commands::Config => {
-            let config = Config::load_from_file()?;
-            config.display();
+            let config_path = services.config_path()?;
+            let config = services.load_config()?;
+            config.display(&config_path);
 
-            let scheduler =
-                scheduler::TaskScheduler::new().context("Failed to create task scheduler")?;
+            let scheduler = services
+                .scheduler()
+                .context("Failed to create task scheduler")?;
 
             println!(
                 "\nTask Status: {}",
@@ -129,33 +534,1603 @@ fn run() -> Result<()> {
                 }
             );
 
-            // Show VBScript path and status
-            if let Ok(vbs_path) = scheduler::TaskScheduler::get_vbs_path() {
-                let vbs_exists = vbs_path.exists();
+            // Show helper file paths and status
+            if let Ok(files) = scheduler.installed_files() {
+                for (label, path) in files {
+                    println!(
+                        "{}: {} {}",
+                        label,
+                        path.display(),
+                        if path.exists() {
+                            "(exists)"
+                        } else {
+                            "(missing)"
+                        }
+                    );
+                }
+            }
+
+            // Show audio backend and the controlled microphone
+            let backend = services.backend()?;
+            println!("\nAudio Backend: {}", backend.name());
+            let endpoints = match resolve_endpoints(backend, &config, None, true) {
+                Ok(endpoints) => endpoints,
+                Err(e) => {
+                    println!("Microphone: {:#}", e);
+                    return Ok(());
+                }
+            };
+            let devices = backend.list_devices().unwrap_or_default();
+            for endpoint in &endpoints {
+                if let Some(device) = devices.iter().find(|device| match endpoint {
+                    Endpoint::Default => device.is_default(),
+                    Endpoint::Id(id) => &device.id == id,
+                }) {
+                    println!("Microphone: {}", device.name);
+                }
+
+                // Show current volume
+                if let Ok(volume) = backend.get_volume(endpoint) {
+                    println!("Current Volume: {:.0}%", volume * 100.0);
+                }
+                if backend.capabilities().mute
+                    && let Ok(muted) = backend.get_mute(endpoint)
+                {
+                    println!("Muted: {}", if muted { "yes" } else { "no" });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Set the microphone volume to `target`; with `all_targets`, the stream and
+/// output targets are enforced along with it, even when setting the
+/// microphone fails
+fn enforce_targets(
+    services: &Services,
+    config: &Config,
+    endpoints: &[Endpoint],
+    all_targets: bool,
+    target: VolumeLevel,
+    quiet: bool,
+) -> Result<()> {
+    let backend = services.backend()?;
+    let microphone = set_microphone(backend, config, endpoints, all_targets, target, quiet);
+    let streams = match config.streams.as_slice() {
+        targets @ [_, ..] if all_targets => enforce::apply_streams(backend, targets, quiet),
+        _ => Ok(()),
+    };
+    let output = match &config.output {
+        Some(output) if all_targets => services
+            .output_backend()
+            .and_then(|backend| enforce::apply_output(backend, output, quiet)),
+        _ => Ok(()),
+    };
+    microphone.and(streams).and(output)
+}
+
+/// Whether the microphones, application streams and output device are all at
+/// the targets `enforce_targets` sets
+fn targets_met(
+    services: &Services,
+    config: &Config,
+    endpoints: &[Endpoint],
+    target: VolumeLevel,
+) -> Result<bool> {
+    let backend = services.backend()?;
+    if !microphones_met(backend, config, endpoints, target)? {
+        return Ok(false);
+    }
+    if !config.streams.is_empty() && !enforce::streams_met(backend, &config.streams)? {
+        return Ok(false);
+    }
+    if let Some(output) = &config.output {
+        let backend = services.output_backend()?;
+        let assignment = enforce::output_assignment(backend, output)?;
+        if !enforce::is_met(backend, &assignment)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Assignments of the microphones `set_microphone` sets, for checking them
+fn microphone_plan(
+    backend: &dyn VolumeBackend,
+    config: &Config,
+    endpoints: &[Endpoint],
+    target: VolumeLevel,
+) -> Result<Vec<enforce::Assignment>> {
+    let devices = if !config.devices.is_empty() || endpoints.len() > 1 {
+        backend
+            .list_devices()
+            .context("Failed to list capture devices")?
+    } else {
+        Vec::new()
+    };
+    Ok(enforce::plan(&devices, config, endpoints, target))
+}
+
+/// Whether the microphones are at the targets `set_microphone` sets
+fn microphones_met(
+    backend: &dyn VolumeBackend,
+    config: &Config,
+    endpoints: &[Endpoint],
+    target: VolumeLevel,
+) -> Result<bool> {
+    for assignment in microphone_plan(backend, config, endpoints, target)? {
+        if !enforce::is_met(backend, &assignment)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Volumes, channel levels and mute states the configured targets cover, to
+/// tell whether anything moved since they were checked
+#[derive(Debug, Default, PartialEq)]
+struct Settings {
+    volumes: Vec<f32>,
+    muted: Vec<bool>,
+}
+
+impl Settings {
+    /// Add the volume of the device of `assignment`, and the channel levels
+    /// and mute state when it sets them
+    fn read(
+        &mut self,
+        backend: &dyn VolumeBackend,
+        assignment: &enforce::Assignment,
+    ) -> Result<()> {
+        let endpoint = &assignment.endpoint;
+        self.volumes.push(backend.get_volume(endpoint)?);
+        if assignment.channels.is_some() {
+            let channels = backend.get_channel_volumes(endpoint)?;
+            self.volumes
+                .extend(channels.iter().map(|channel| channel.volume));
+        }
+        if assignment.muted.is_some() {
+            self.muted.push(backend.get_mute(endpoint)?);
+        }
+        Ok(())
+    }
+}
+
+/// Current settings of the microphones, and with `all_targets` of the
+/// application streams and the output device too, as `enforce_targets` sets
+/// them
+fn current_settings(services: &Services, all_targets: bool) -> Result<Settings> {
+    let backend = services.backend()?;
+    let config = services.load_config()?;
+    let endpoints = resolve_endpoints(backend, &config, None, true)?;
+    let mut settings = Settings::default();
+    for assignment in microphone_plan(backend, &config, &endpoints, config.target())? {
+        settings.read(backend, &assignment)?;
+    }
+    if !all_targets {
+        return Ok(settings);
+    }
+    if !config.streams.is_empty() {
+        let streams = backend.list_streams()?;
+        settings
+            .volumes
+            .extend(streams.iter().map(|stream| stream.volume));
+    }
+    if let Some(output) = &config.output {
+        let backend = services.output_backend()?;
+        settings.read(backend, &enforce::output_assignment(backend, output)?)?;
+    }
+    Ok(settings)
+}
+
+/// Enforce the configured targets, re-read from the config file, if any of
+/// them is not met; returns whether anything was set
+fn restore_targets(services: &Services, quiet: bool) -> Result<bool> {
+    let config = services.load_config()?;
+    let endpoints = resolve_endpoints(services.backend()?, &config, None, quiet)?;
+    let target = config.target();
+    if targets_met(services, &config, &endpoints, target)? {
+        return Ok(false);
+    }
+    enforce_targets(services, &config, &endpoints, true, target, quiet)?;
+    Ok(true)
+}
+
+/// Wait up to `timeout` for changes, then restore the targets if they drifted
+///
+/// Notifications of our own writes alone do not trigger a check, so restoring
+/// never answers its own echoes; `echoes` checks once more after them, in
+/// case someone else's change was taken for ours. Failures to restore are
+/// reported and watching goes on.
+fn watch_step(
+    services: &Services,
+    watcher: &mut Watcher,
+    echoes: &mut EchoFilter<Settings>,
+    timeout: Duration,
+    quiet: bool,
+) -> Result<()> {
+    let events = watcher.wait(echoes.timeout(timeout, Instant::now()))?;
+    if events.iter().any(VolumeEvent::is_device_change) {
+        // Devices that arrived report their volume changes once subscribed
+        watcher.renew()?;
+    }
+    match echoes.check(&events, Instant::now()) {
+        Check::Skip => return Ok(()),
+        Check::IfMoved => {
+            if current_settings(services, true).is_ok_and(|settings| !echoes.moved(&settings)) {
+                return Ok(());
+            }
+        }
+        Check::Targets => {}
+    }
+    if let Err(e) = restore_targets(services, quiet) {
+        eprintln!("Error: {:#}", e);
+    }
+    echoes.settled(current_settings(services, true).ok());
+    Ok(())
+}
+
+/// Set the microphones to their targets, re-read from the config file, if
+/// they are not at them; returns whether anything was set
+fn hold_microphone(services: &Services, quiet: bool) -> Result<bool> {
+    let backend = services.backend()?;
+    let config = services.load_config()?;
+    let endpoints = resolve_endpoints(backend, &config, None, quiet)?;
+    let target = config.target();
+    if microphones_met(backend, &config, &endpoints, target)? {
+        return Ok(false);
+    }
+    set_microphone(backend, &config, &endpoints, true, target, quiet)?;
+    Ok(true)
+}
+
+/// Wait up to `timeout` for changes, then put the microphones back to their
+/// targets if another application moved them
+///
+/// When `detector` finds the changes come too often to be one-offs, the
+/// volume is left alone for the back-off before it is restored, and every
+/// situation is logged. Notifications of our own writes are left to
+/// `echoes`, and never count towards a fight. Failures to restore are
+/// reported and locking goes on.
+fn lock_step(
+    services: &Services,
+    watcher: &mut Watcher,
+    echoes: &mut EchoFilter<Settings>,
+    detector: &mut FightDetector,
+    timeout: Duration,
+    quiet: bool,
+) -> Result<()> {
+    let events = watcher.wait(echoes.timeout(timeout, Instant::now()))?;
+    if events.iter().any(VolumeEvent::is_device_change) {
+        watcher.renew()?;
+    }
+    match echoes.check(&events, Instant::now()) {
+        Check::Skip => return Ok(()),
+        Check::IfMoved => {
+            if current_settings(services, false).is_ok_and(|settings| !echoes.moved(&settings)) {
+                return Ok(());
+            }
+        }
+        Check::Targets => {}
+    }
+
+    // Changes that leave the microphones at their targets, e.g. of a stream,
+    // are no fight
+    let moved = services.load_config().and_then(|config| {
+        let backend = services.backend()?;
+        let endpoints = resolve_endpoints(backend, &config, None, quiet)?;
+        microphones_met(backend, &config, &endpoints, config.target()).map(|met| !met)
+    });
+    match moved {
+        Ok(true) => {}
+        Ok(false) => {
+            echoes.settled(current_settings(services, false).ok());
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return Ok(());
+        }
+    }
+
+    match detector.external_change(Instant::now()) {
+        Response::Restore => {
+            if !quiet {
+                println!("Microphone volume changed by another application, restoring");
+            }
+        }
+        Response::BackOff(backoff) => {
+            if !quiet {
+                println!(
+                    "Another application keeps changing the microphone volume, backing off for {:?}",
+                    backoff
+                );
+            }
+            let resume = Instant::now() + backoff;
+            while let Some(left) = resume.checked_duration_since(Instant::now())
+                && !left.is_zero()
+            {
+                if watcher
+                    .wait(left)?
+                    .iter()
+                    .any(VolumeEvent::is_device_change)
+                {
+                    watcher.renew()?;
+                }
+            }
+            if !quiet {
+                println!("Back-off over, restoring the microphone volume");
+            }
+        }
+    }
+    if let Err(e) = hold_microphone(services, quiet) {
+        eprintln!("Error: {:#}", e);
+    }
+    echoes.settled(current_settings(services, false).ok());
+    Ok(())
+}
+
+/// Wait up to `timeout` for device changes, then apply the configured targets
+/// to the microphones that arrived or became a default
+///
+/// `devices` is the device list the changes are found against, and is
+/// updated. Failures to apply are reported and monitoring goes on.
+fn monitor_step(
+    services: &Services,
+    watcher: &Watcher,
+    devices: &mut Vec<DeviceInfo>,
+    timeout: Duration,
+    quiet: bool,
+) -> Result<()> {
+    let events = watcher.wait(timeout)?;
+    if !events.iter().any(VolumeEvent::is_device_change) {
+        return Ok(());
+    }
+
+    let backend = services.backend()?;
+    let current = backend
+        .list_devices()
+        .context("Failed to list capture devices")?;
+    let changes = DeviceChanges::between(devices, &current);
+    *devices = current;
+
+    if !quiet {
+        for device in &changes.added {
+            println!("Connected: {}", device.name);
+        }
+        for device in &changes.removed {
+            println!("Disconnected: {}", device.name);
+        }
+        for device in &changes.new_defaults {
+            let roles: Vec<String> = device.default_for.iter().map(ToString::to_string).collect();
+            println!("New default ({}): {}", roles.join(", "), device.name);
+        }
+    }
+
+    let arrived: Vec<&str> = changes.to_apply().collect();
+    if arrived.is_empty() {
+        return Ok(());
+    }
+    if let Err(e) = apply_to_arrivals(services, devices, &arrived, quiet) {
+        eprintln!("Error: {:#}", e);
+    }
+    Ok(())
+}
+
+/// Enforce the configured targets, re-read from the config file, on the
+/// present `devices` whose ID is in `arrived` only
+fn apply_to_arrivals(
+    services: &Services,
+    devices: &[DeviceInfo],
+    arrived: &[&str],
+    quiet: bool,
+) -> Result<()> {
+    let backend = services.backend()?;
+    let config = services.load_config()?;
+    let endpoints = resolve_endpoints(backend, &config, None, quiet)?;
+    let assignments: Vec<_> = enforce::plan(devices, &config, &endpoints, config.target())
+        .into_iter()
+        .filter(|assignment| {
+            let device = match &assignment.endpoint {
+                Endpoint::Default => devices.iter().find(|device| device.is_default()),
+                Endpoint::Id(id) => devices.iter().find(|device| &device.id == id),
+            };
+            device.is_some_and(|device| arrived.contains(&device.id.as_str()))
+        })
+        .collect();
+    enforce::apply(backend, &assignments, quiet)
+}
+
+/// Set the microphone volume to `target`, with the channels and mute state the
+/// config enforces; with `all_targets`, the `[[devices]]` targets are enforced
+/// too
+fn set_microphone(
+    backend: &dyn VolumeBackend,
+    config: &Config,
+    endpoints: &[Endpoint],
+    all_targets: bool,
+    target: VolumeLevel,
+    quiet: bool,
+) -> Result<()> {
+    if all_targets && (!config.devices.is_empty() || endpoints.len() > 1) {
+        // Enforce the per-device targets along with the main ones
+        let devices = backend
+            .list_devices()
+            .context("Failed to list capture devices")?;
+        let assignments = enforce::plan(&devices, config, endpoints, target);
+        return enforce::apply(backend, &assignments, quiet);
+    }
+
+    // With no device list, no `[[devices]]` entry applies
+    let assignment = enforce::plan(&[], config, &endpoints[..1], target).remove(0);
+    let outcome = enforce::apply_one(backend, &assignment)?;
+    if !quiet {
+        match outcome {
+            Outcome::Set(level, None) => println!("Microphone volume set to: {}", level),
+            Outcome::Set(level, Some(clamp)) => {
+                println!("Microphone volume set to: {} ({})", level, clamp)
+            }
+            Outcome::Interrupted(level) => {
                 println!(
-                    "VBScript: {} {}",
-                    vbs_path.display(),
-                    if vbs_exists { "(exists)" } else { "(missing)" }
+                    "Microphone volume changed by someone else while ramping to {}, left \
+                     alone{}",
+                    level,
+                    assignment.skipped()
                 );
+                return Ok(());
             }
+            _ => println!("Microphone volume already {}", assignment.compliance()),
+        }
+        if let Some(channels) = &config.channels {
+            println!("Microphone channels: {}", channels);
+        }
+        match config.muted {
+            Some(true) => println!("Microphone kept muted"),
+            Some(false) => println!("Microphone kept unmuted"),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Endpoints a command acts on: the device named by the `--device` selector,
+/// or else by `device` in the config; the default devices of the configured
+/// role when neither is set
+fn resolve_endpoints(
+    backend: &dyn VolumeBackend,
+    config: &Config,
+    device: Option<&str>,
+    quiet: bool,
+) -> Result<Vec<Endpoint>> {
+    let Some(selector) = device.or(config.device.as_deref()) else {
+        return role_endpoints(backend, config.role);
+    };
 
-            // Show current volume
-            if let Ok(volume) = audio::AudioController::get_current_volume() {
-                println!("\nCurrent Volume: {:.0}%", volume * 100.0);
+    let devices = backend
+        .list_devices()
+        .context("Failed to list capture devices")?;
+    match audio::find_device(&devices, selector)? {
+        Some(device) => Ok(vec![Endpoint::Id(device.id)]),
+        None if config.fallback_to_default => {
+            if !quiet {
+                eprintln!(
+                    "Warning: no device matches '{}', using the default device",
+                    selector
+                );
             }
+            role_endpoints(backend, config.role)
+        }
+        None => anyhow::bail!(
+            "No present capture device matches '{}' (see `devices` for IDs and names)",
+            selector
+        ),
+    }
+}
+
+/// Default devices of the roles `role` selects, without duplicates
+///
+/// The console default is the backend's own default device. Other roles are
+/// looked up in the device list; a role no device is the default for falls
+/// back to the default device, as on platforms with a single default.
+fn role_endpoints(backend: &dyn VolumeBackend, role: DeviceRole) -> Result<Vec<Endpoint>> {
+    if role == DeviceRole::Console {
+        return Ok(vec![Endpoint::Default]);
+    }
+
+    let devices = backend
+        .list_devices()
+        .context("Failed to list capture devices")?;
+    let mut endpoints = Vec::new();
+    for role in role.roles() {
+        let endpoint = devices
+            .iter()
+            .find(|device| device.default_for.contains(role))
+            .map_or(Endpoint::Default, |device| Endpoint::Id(device.id.clone()));
+        if !endpoints.contains(&endpoint) {
+            endpoints.push(endpoint);
         }
     }
+    Ok(endpoints)
+}
+
+/// Mute or unmute the selected microphone; `None` toggles its current state
+fn change_mute(
+    services: &Services,
+    device: Option<&str>,
+    muted: Option<bool>,
+    quiet: bool,
+) -> Result<()> {
+    let backend = services.backend()?;
+    let config = services.load_config()?;
+    let endpoints = resolve_endpoints(backend, &config, device, quiet)?;
+
+    for endpoint in &endpoints {
+        let muted = match muted {
+            Some(muted) => muted,
+            None => !backend
+                .get_mute(endpoint)
+                .context("Failed to get mute state")?,
+        };
+        backend
+            .set_mute(endpoint, muted)
+            .context("Failed to set mute state")?;
 
+        if !quiet {
+            let state = if muted { "muted" } else { "unmuted" };
+            match endpoints.len() {
+                1 => println!("Microphone {}", state),
+                _ => println!("{}: microphone {}", endpoint, state),
+            }
+        }
+    }
     Ok(())
 }
 
+/// Print one `devices` entry; the default device is marked with `*`
+fn print_device(device: &DeviceInfo) {
+    let marker = if device.is_default() { "*" } else { " " };
+    println!("{} {}", marker, device.name);
+    println!("    ID: {}", device.id);
+    println!("    State: {}", device.state);
+    match device.volume {
+        Some(volume) => println!("    Volume: {:.0}%", volume * 100.0),
+        None => println!("    Volume: unknown"),
+    }
+    match device.muted {
+        Some(muted) => println!("    Muted: {}", if muted { "yes" } else { "no" }),
+        None => println!("    Muted: unknown"),
+    }
+    if !device.default_for.is_empty() {
+        let roles: Vec<String> = device.default_for.iter().map(|r| r.to_string()).collect();
+        println!("    Default for: {}", roles.join(", "));
+    }
+}
+
+/// Print one `streams` entry, with the `[[streams]]` target it matches
+fn print_stream(stream: &StreamInfo, target: Option<&StreamTarget>) {
+    println!("  {}", stream.application);
+    println!("    ID: {}", stream.id);
+    println!("    Volume: {:.0}%", stream.volume * 100.0);
+    match stream.muted {
+        Some(muted) => println!("    Muted: {}", if muted { "yes" } else { "no" }),
+        None => println!("    Muted: unknown"),
+    }
+    if let Some(target) = target {
+        println!("    Target: {}", VolumeLevel::Scalar(target.target_volume));
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use tempfile::TempDir;
+
     use super::*;
+    use crate::audio::Role;
+
+    fn mock_services(backend: &MockBackend, scheduler: &MockScheduler) -> (Services, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let services = Services {
+            backend: OnceCell::from(Box::new(backend.clone()) as Box<dyn VolumeBackend>),
+            backend_kind: Some(BackendKind::Mock),
+            output_backend: OnceCell::new(),
+            mock_scheduler: Some(scheduler.clone()),
+            config_path: Some(dir.path().join("config.toml")),
+            role: None,
+            dry_run: false,
+            quiet: true,
+        };
+        (services, dir)
+    }
+
+    /// `mock_services` for a `--dry-run` command
+    fn dry_run_services(backend: &MockBackend, scheduler: &MockScheduler) -> (Services, TempDir) {
+        let (mut services, dir) = mock_services(backend, scheduler);
+        let backend = services.backend.take().unwrap();
+        services.dry_run = true;
+        services.backend = OnceCell::from(services.guard(backend));
+        (services, dir)
+    }
 
     #[test]
+    #[cfg(windows)]
     fn test_com_guard() {
         let guard = ComGuard::new();
         assert!(guard.is_ok());
     }
+
+    #[test]
+    fn test_volume_set() {
+        let backend = MockBackend::new().with_device("Mic", 0.3, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        execute(
+            Commands::Volume {
+                level: Some("85".parse().unwrap()),
+                db: None,
+                device: None,
+            },
+            true,
+            &services,
+        )
+        .unwrap();
+
+        assert!((backend.devices()[0].volume - 0.85).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_volume_get() {
+        let backend = MockBackend::new().with_device("Mic", 0.3, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        execute(
+            Commands::Volume {
+                level: None,
+                db: None,
+                device: None,
+            },
+            true,
+            &services,
+        )
+        .unwrap();
+
+        assert_eq!(backend.devices()[0].volume, 0.3);
+    }
+
+    #[test]
+    fn test_volume_backend_failure() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.3, false)
+            .with_failure(audio::Operation::SetVolume);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        let err = execute(
+            Commands::Volume {
+                level: Some("50".parse().unwrap()),
+                db: None,
+                device: None,
+            },
+            true,
+            &services,
+        )
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("Injected set_volume failure"));
+        assert_eq!(backend.devices()[0].volume, 0.3);
+    }
+
+    #[test]
+    fn test_volume_skips_compliant_devices() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.9, false)
+            .with_failure(audio::Operation::SetVolume);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let set_volume = |level: &str| {
+            execute(
+                Commands::Volume {
+                    level: Some(level.parse().unwrap()),
+                    db: None,
+                    device: None,
+                },
+                true,
+                &services,
+            )
+        };
+
+        // A device already at the target is not written
+        set_volume("90").unwrap();
+
+        // Nor one the policy allows
+        std::fs::write(
+            services.config_path().unwrap(),
+            "policy = { mode = \"floor\" }\n",
+        )
+        .unwrap();
+        set_volume("80").unwrap();
+        assert!(set_volume("95").is_err());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "policy = { mode = \"range\", min = 0.5, max = 0.95 }\n",
+        )
+        .unwrap();
+        set_volume("50").unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.9);
+    }
+
+    #[test]
+    fn test_volume_ramps() {
+        let backend = MockBackend::new().with_device("Mic", 0.4, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "[ramp]\nduration_ms = 100\ncurve = \"ease-out\"\n",
+        )
+        .unwrap();
+        let set_volume = |level: &str| {
+            execute(
+                Commands::Volume {
+                    level: Some(level.parse().unwrap()),
+                    db: None,
+                    device: None,
+                },
+                true,
+                &services,
+            )
+        };
+
+        let started = Instant::now();
+        set_volume("90").unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!((backend.devices()[0].volume - 0.9).abs() < 1e-4);
+
+        set_volume("-10").unwrap();
+        assert!((backend.devices()[0].volume - 0.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_volume_limits() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "target_volume = 1.0\nmax_volume = 0.9\nmax_step_per_run = 0.25\n",
+        )
+        .unwrap();
+        let set_volume = |level: &str| {
+            execute(
+                Commands::Volume {
+                    level: Some(level.parse().unwrap()),
+                    db: None,
+                    device: None,
+                },
+                true,
+                &services,
+            )
+        };
+        let volume = || backend.devices()[0].volume;
+
+        // Each command moves at most one step, and never past the cap
+        set_volume("100").unwrap();
+        assert!((volume() - 0.75).abs() < 1e-6);
+        set_volume("100").unwrap();
+        assert!((volume() - 0.9).abs() < 1e-6);
+        set_volume("+5").unwrap();
+        assert!((volume() - 0.9).abs() < 1e-6);
+        set_volume("-50").unwrap();
+        assert!((volume() - 0.65).abs() < 1e-6);
+
+        // A watch run is held to the cap as well, and then counts as met
+        assert!(restore_targets(&services, true).unwrap());
+        assert!((volume() - 0.9).abs() < 1e-6);
+        assert!(!restore_targets(&services, true).unwrap());
+
+        // A limit that cannot be compared with the target refuses to set it
+        assert!(set_volume("-6dB").is_err());
+        assert!((volume() - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_volume_dry_run() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.5, false)
+            .with_stream("Zoom", 0.3);
+        let output = MockBackend::new().with_device("Speakers", 0.4, false);
+        let (services, _dir) = dry_run_services(&backend, &MockScheduler::new());
+        services
+            .output_backend
+            .set(services.guard(Box::new(output.clone())))
+            .ok()
+            .unwrap();
+        std::fs::write(
+            services.config_path().unwrap(),
+            "muted = true\n[ramp]\nduration_ms = 500\n[[streams]]\napplication = \"Zoom\"\n\
+             target_volume = 0.9\n[output]\ntarget_volume = 0.6\n",
+        )
+        .unwrap();
+
+        let set_volume = |level: &str| {
+            execute(
+                Commands::Volume {
+                    level: Some(level.parse().unwrap()),
+                    db: None,
+                    device: None,
+                },
+                true,
+                &services,
+            )
+        };
+        set_volume("80").unwrap();
+        set_volume("+10").unwrap();
+        assert!(restore_targets(&services, true).unwrap());
+        assert_eq!(backend.devices()[0].volume, 0.5);
+        assert!(!backend.devices()[0].muted);
+        assert_eq!(backend.streams()[0].volume, 0.3);
+        assert_eq!(output.devices()[0].volume, 0.4);
+
+        // Invalid changes still fail
+        assert!(set_volume("6dB").is_err());
+    }
+
+    #[test]
+    fn test_volume_without_device() {
+        let (services, _dir) = mock_services(&MockBackend::new(), &MockScheduler::new());
+
+        assert!(
+            execute(
+                Commands::Volume {
+                    level: None,
+                    db: None,
+                    device: None,
+                },
+                true,
+                &services
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_volume_device_selector() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, false)
+            .with_device("Yeti USB Microphone", 0.8, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        let set = |device: &str| Commands::Volume {
+            level: Some("60".parse().unwrap()),
+            db: None,
+            device: Some(device.to_string()),
+        };
+        execute(set("yeti"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.4);
+        assert_eq!(backend.devices()[1].volume, 0.6);
+
+        // The configured device applies when no flag is given
+        let config_path = services.config_path().unwrap();
+        std::fs::write(&config_path, "device = \"mock-0\"\n").unwrap();
+        let set_default = Commands::Volume {
+            level: Some("30".parse().unwrap()),
+            db: None,
+            device: None,
+        };
+        execute(set_default, true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.3);
+
+        // An absent device is an error unless falling back is configured
+        assert!(execute(set("webcam"), true, &services).is_err());
+        std::fs::write(&config_path, "fallback_to_default = true\n").unwrap();
+        execute(set("webcam"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.6);
+    }
+
+    #[test]
+    fn test_volume_device_targets() {
+        let backend = MockBackend::new()
+            .with_device("Microphone Array", 0.4, false)
+            .with_device("Headset Microphone", 0.4, false)
+            .with_device("Webcam", 0.4, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "[[devices]]\ndevice = \"Headset\"\ntarget_volume = 0.85\n",
+        )
+        .unwrap();
+
+        let set = |device: Option<&str>| Commands::Volume {
+            level: Some("90".parse().unwrap()),
+            db: None,
+            device: device.map(str::to_string),
+        };
+        execute(set(None), true, &services).unwrap();
+        let devices = backend.devices();
+        assert_eq!(devices[0].volume, 0.9);
+        assert_eq!(devices[1].volume, 0.85);
+        assert_eq!(devices[2].volume, 0.4);
+
+        // An explicit device only touches that device
+        execute(set(Some("Headset")), true, &services).unwrap();
+        assert_eq!(backend.devices()[1].volume, 0.9);
+    }
+
+    #[test]
+    fn test_mute_commands() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, false)
+            .with_device("USB Mic", 0.8, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        execute(Commands::Mute { device: None }, true, &services).unwrap();
+        assert!(backend.devices()[0].muted);
+
+        execute(Commands::Unmute { device: None }, true, &services).unwrap();
+        assert!(!backend.devices()[0].muted);
+
+        let usb = Some("USB".to_string());
+        execute(
+            Commands::ToggleMute {
+                device: usb.clone(),
+            },
+            true,
+            &services,
+        )
+        .unwrap();
+        assert!(backend.devices()[1].muted);
+        execute(Commands::ToggleMute { device: usb }, true, &services).unwrap();
+        assert!(!backend.devices()[1].muted);
+        assert!(!backend.devices()[0].muted);
+    }
+
+    #[test]
+    fn test_device_role() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, false)
+            .with_device("Headset", 0.6, false)
+            .with_default_for(Role::Communications);
+        let (mut services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let config_path = services.config_path().unwrap();
+        let set = |level: &str| Commands::Volume {
+            level: Some(level.parse().unwrap()),
+            db: None,
+            device: None,
+        };
+
+        std::fs::write(&config_path, "role = \"communications\"\n").unwrap();
+        execute(set("90"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.4);
+        assert_eq!(backend.devices()[1].volume, 0.9);
+
+        // The command-line role overrides the configured one
+        services.role = Some(DeviceRole::Console);
+        execute(set("80"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+        assert_eq!(backend.devices()[1].volume, 0.9);
+
+        services.role = Some(DeviceRole::All);
+        execute(set("70"), true, &services).unwrap();
+        execute(Commands::Mute { device: None }, true, &services).unwrap();
+        for device in backend.devices() {
+            assert_eq!(device.volume, 0.7);
+            assert!(device.muted);
+        }
+        execute(set("+10"), true, &services).unwrap();
+        assert!(
+            backend
+                .devices()
+                .iter()
+                .all(|d| (d.volume - 0.8).abs() < 1e-6)
+        );
+
+        // A selected device wins over the role
+        let usb = Commands::Volume {
+            level: Some("50".parse().unwrap()),
+            db: None,
+            device: Some("Built-in".to_string()),
+        };
+        execute(usb, true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.5);
+        assert!((backend.devices()[1].volume - 0.8).abs() < 1e-6);
+
+        // install saves the command-line role
+        let install = Commands::Install {
+            volume: "80".parse().unwrap(),
+            db: None,
+            interval: 5,
+            device: None,
+        };
+        execute(install, true, &services).unwrap();
+        assert_eq!(Config::load(&config_path).unwrap().role, DeviceRole::All);
+    }
+
+    #[test]
+    fn test_volume_enforces_mute_state() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, true)
+            .with_device("Headset", 0.4, true);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let config_path = services.config_path().unwrap();
+        let set = || Commands::Volume {
+            level: Some("90".parse().unwrap()),
+            db: None,
+            device: None,
+        };
+
+        std::fs::write(&config_path, "muted = false\n").unwrap();
+        execute(set(), true, &services).unwrap();
+        assert!(!backend.devices()[0].muted);
+        assert!(backend.devices()[1].muted);
+
+        std::fs::write(
+            &config_path,
+            "muted = true\n[[devices]]\ndevice = \"Headset\"\ntarget_volume = 0.5\nmuted = false\n",
+        )
+        .unwrap();
+        execute(set(), true, &services).unwrap();
+        assert!(backend.devices()[0].muted);
+        assert!(!backend.devices()[1].muted);
+    }
+
+    #[test]
+    fn test_volume_enforces_channels() {
+        let backend = MockBackend::new()
+            .with_device("Interface", 0.9, false)
+            .with_channels(&[0.9, 0.6])
+            .with_device("Headset", 0.4, false)
+            .with_channels(&[0.4, 0.2]);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let config_path = services.config_path().unwrap();
+        let set = || Commands::Volume {
+            level: Some("80".parse().unwrap()),
+            db: None,
+            device: None,
+        };
+
+        std::fs::write(&config_path, "channels = \"balanced\"\n").unwrap();
+        execute(set(), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].channels, [0.8, 0.8]);
+        assert_eq!(backend.devices()[1].channels, [0.4, 0.2]);
+
+        std::fs::write(
+            &config_path,
+            "channels = [0.7, 0.75]\n[[devices]]\ndevice = \"Headset\"\ntarget_volume = 0.5\nchannels = \"balanced\"\n",
+        )
+        .unwrap();
+        execute(set(), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].channels, [0.7, 0.75]);
+        assert_eq!(backend.devices()[1].channels, [0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_volume_enforces_output() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let speakers = MockBackend::new()
+            .with_device("Speakers", 0.2, true)
+            .with_device("Headphones", 0.9, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        services
+            .output_backend
+            .set(Box::new(speakers.clone()))
+            .unwrap_or_else(|_| unreachable!());
+        let config_path = services.config_path().unwrap();
+        let set = |device: Option<&str>| Commands::Volume {
+            level: Some(if device.is_some() { "70" } else { "80" }.parse().unwrap()),
+            db: None,
+            device: device.map(str::to_string),
+        };
+
+        // Output devices are left alone without an [output] section
+        execute(set(None), true, &services).unwrap();
+        assert_eq!(speakers.devices()[0].volume, 0.2);
+
+        std::fs::write(
+            &config_path,
+            "[output]\ntarget_volume = 0.4\nmuted = false\n",
+        )
+        .unwrap();
+        execute(set(None), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+        assert_eq!(speakers.devices()[0].volume, 0.4);
+        assert!(!speakers.devices()[0].muted);
+        assert_eq!(speakers.devices()[1].volume, 0.9);
+
+        // Picking a microphone sets only that microphone
+        std::fs::write(&config_path, "[output]\ntarget_volume = 0.6\n").unwrap();
+        execute(set(Some("Mic")), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.7);
+        assert_eq!(speakers.devices()[0].volume, 0.4);
+
+        // A failing output device does not keep the microphone from being set
+        std::fs::write(
+            &config_path,
+            "target_volume = 0.3\n[output]\ntarget_volume = 0.6\ndevice = \"HDMI\"\n",
+        )
+        .unwrap();
+        let err = execute(set(None), true, &services).unwrap_err();
+        assert!(format!("{:#}", err).contains("No present output device matches 'HDMI'"));
+        assert_eq!(backend.devices()[0].volume, 0.8);
+        assert_eq!(speakers.devices()[1].volume, 0.9);
+
+        execute(Commands::Devices { output: true }, true, &services).unwrap();
+    }
+
+    #[test]
+    fn test_volume_enforces_streams() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.5, false)
+            .with_stream("Firefox", 0.3)
+            .with_stream("OBS", 0.6);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let config_path = services.config_path().unwrap();
+        std::fs::write(
+            &config_path,
+            "[[streams]]\napplication = \"firefox\"\ntarget_volume = \"90%\"\n",
+        )
+        .unwrap();
+        let set = |device: Option<&str>| Commands::Volume {
+            level: Some("80".parse().unwrap()),
+            db: None,
+            device: device.map(str::to_string),
+        };
+
+        execute(set(Some("Mic")), true, &services).unwrap();
+        assert_eq!(backend.streams()[0].volume, 0.3);
+
+        execute(set(None), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+        assert_eq!(backend.streams()[0].volume, 0.9);
+        assert_eq!(backend.streams()[1].volume, 0.6);
+
+        execute(Commands::Streams, false, &services).unwrap();
+        let _failing = backend.with_failure(audio::Operation::ListDevices);
+        assert!(execute(Commands::Streams, true, &services).is_err());
+    }
+
+    #[test]
+    fn test_watch_restores_targets() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.5, false)
+            .with_stream("Firefox", 0.5);
+        let speakers = MockBackend::new().with_device("Speakers", 0.2, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        services
+            .output_backend
+            .set(Box::new(speakers.clone()))
+            .unwrap_or_else(|_| unreachable!());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "target_volume = 0.8\nmuted = false\n\
+             [[streams]]\napplication = \"Firefox\"\ntarget_volume = 0.9\n\
+             [output]\ntarget_volume = 0.4\n",
+        )
+        .unwrap();
+        let mut watcher = Watcher::new(&[&backend, &speakers]).unwrap();
+        let mut echoes = EchoFilter::new(Duration::from_millis(100));
+
+        assert!(restore_targets(&services, true).unwrap());
+        assert!(!restore_targets(&services, true).unwrap());
+
+        // Someone else changes the microphone, a stream and the speakers
+        backend
+            .external()
+            .set_mute(&Endpoint::Default, true)
+            .unwrap();
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        assert!(!backend.devices()[0].muted);
+
+        backend
+            .external()
+            .set_stream_volume("stream-0", 0.1)
+            .unwrap();
+        speakers
+            .external()
+            .set_volume(&Endpoint::Default, 1.0)
+            .unwrap();
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        assert_eq!(backend.streams()[0].volume, 0.9);
+        assert_eq!(speakers.devices()[0].volume, 0.4);
+
+        // A change reported as ours is not answered right away, but checked
+        // once more later: on Linux another application's change right after
+        // one of our writes is reported so
+        backend.set_volume(&Endpoint::Default, 0.3).unwrap();
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.3);
+        let recheck = Duration::from_secs(5);
+        let started = Instant::now();
+        watch_step(&services, &mut watcher, &mut echoes, recheck, true).unwrap();
+        assert!(started.elapsed() < recheck);
+        assert_eq!(backend.devices()[0].volume, 0.8);
+
+        // The echo of that restore is skipped, and nothing moved since
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        watch_step(&services, &mut watcher, &mut echoes, recheck, true).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+    }
+
+    #[test]
+    fn test_watch_steps_once_per_run() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "target_volume = 0.8\nmax_step_per_run = 0.1\n",
+        )
+        .unwrap();
+        let mut watcher = Watcher::new(&[&backend]).unwrap();
+        let mut echoes = EchoFilter::new(Duration::from_millis(100));
+        let volume = || backend.devices()[0].volume;
+
+        assert!(restore_targets(&services, true).unwrap());
+        echoes.settled(current_settings(&services, true).ok());
+        assert!((volume() - 0.6).abs() < 1e-6);
+
+        // The notifications of the step and the re-check after them do not
+        // step again
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        watch_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            Duration::from_secs(5),
+            true,
+        )
+        .unwrap();
+        assert!((volume() - 0.6).abs() < 1e-6);
+
+        // The periodic check and someone else's change do
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        assert!((volume() - 0.7).abs() < 1e-6);
+        backend
+            .external()
+            .set_mute(&Endpoint::Default, true)
+            .unwrap();
+        watch_step(&services, &mut watcher, &mut echoes, Duration::ZERO, true).unwrap();
+        assert!((volume() - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_lock_backs_off() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.5, false)
+            .with_stream("Zoom", 0.5);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(services.config_path().unwrap(), "target_volume = 0.8\n").unwrap();
+        let mut watcher = Watcher::new(&[&backend]).unwrap();
+        let backoff = Duration::from_millis(100);
+        let mut detector = FightDetector::new(2, Duration::from_secs(60), backoff, backoff * 4);
+        let mut echoes = EchoFilter::new(Duration::from_millis(100));
+
+        assert!(hold_microphone(&services, true).unwrap());
+        assert!(!hold_microphone(&services, true).unwrap());
+        let conferencing = backend.external();
+
+        // A one-off change is restored right away
+        conferencing.set_volume(&Endpoint::Default, 0.3).unwrap();
+        lock_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            &mut detector,
+            Duration::ZERO,
+            true,
+        )
+        .unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+
+        // A change to a stream is no fight
+        conferencing.set_stream_volume("stream-0", 0.2).unwrap();
+        lock_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            &mut detector,
+            Duration::ZERO,
+            true,
+        )
+        .unwrap();
+
+        // The second change within the window is a fight: the volume is
+        // restored once the back-off is over
+        conferencing.set_volume(&Endpoint::Default, 0.3).unwrap();
+        let started = Instant::now();
+        lock_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            &mut detector,
+            Duration::ZERO,
+            true,
+        )
+        .unwrap();
+        assert!(started.elapsed() >= backoff);
+        assert_eq!(backend.devices()[0].volume, 0.8);
+
+        // Notifications of our own write are left alone, and the fight starts
+        // over from the next change
+        lock_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            &mut detector,
+            Duration::ZERO,
+            true,
+        )
+        .unwrap();
+        conferencing.set_volume(&Endpoint::Default, 0.3).unwrap();
+        lock_step(
+            &services,
+            &mut watcher,
+            &mut echoes,
+            &mut detector,
+            Duration::ZERO,
+            true,
+        )
+        .unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.8);
+    }
+
+    #[test]
+    fn test_lock_rechecks_changes_in_own_write_window() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(services.config_path().unwrap(), "target_volume = 0.8\n").unwrap();
+        let mut watcher = Watcher::new(&[&backend]).unwrap();
+        let mut echoes = EchoFilter::new(Duration::from_millis(100));
+        let backoff = Duration::from_millis(200);
+        let mut detector = FightDetector::new(2, Duration::from_secs(60), backoff, backoff * 4);
+        let mut step = |timeout| {
+            lock_step(
+                &services,
+                &mut watcher,
+                &mut echoes,
+                &mut detector,
+                timeout,
+                true,
+            )
+            .unwrap()
+        };
+        let recheck = Duration::from_secs(5);
+        assert!(hold_microphone(&services, true).unwrap());
+
+        // Another application's change within the window of our write is
+        // reported as ours, like the Linux backends do: it is left alone
+        // until the re-check
+        backend.set_volume(&Endpoint::Default, 0.3).unwrap();
+        step(Duration::ZERO);
+        assert_eq!(backend.devices()[0].volume, 0.3);
+        step(recheck);
+        assert_eq!(backend.devices()[0].volume, 0.8);
+
+        // The echo of the restore is no change of anyone's
+        step(Duration::ZERO);
+        let started = Instant::now();
+        step(recheck);
+        assert!(started.elapsed() < backoff);
+
+        // A change found by the re-check counts towards a fight
+        backend.set_volume(&Endpoint::Default, 0.3).unwrap();
+        step(Duration::ZERO);
+        let started = Instant::now();
+        step(recheck);
+        assert!(started.elapsed() >= backoff);
+        assert_eq!(backend.devices()[0].volume, 0.8);
+    }
+
+    #[test]
+    fn test_monitor_applies_to_arrivals() {
+        let backend = MockBackend::new().with_device("Built-in", 0.5, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        std::fs::write(
+            services.config_path().unwrap(),
+            "target_volume = 0.8\n[[devices]]\ndevice = \"*Headset*\"\ntarget_volume = 0.6\n",
+        )
+        .unwrap();
+        let watcher = Watcher::new(&[&backend]).unwrap();
+        let mut devices = backend.list_devices().unwrap();
+
+        // Volume changes alone are left to `watch`
+        backend.set_volume(&Endpoint::Default, 0.2).unwrap();
+        monitor_step(&services, &watcher, &mut devices, Duration::ZERO, true).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.2);
+
+        backend.clone().with_device("USB Headset", 1.0, false);
+        monitor_step(&services, &watcher, &mut devices, Duration::ZERO, true).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(backend.devices()[1].volume, 0.6);
+        assert_eq!(backend.devices()[0].volume, 0.2);
+
+        // A device that becomes the default gets the main target
+        backend.clone().with_device("Webcam", 0.1, false);
+        monitor_step(&services, &watcher, &mut devices, Duration::ZERO, true).unwrap();
+        assert_eq!(backend.devices()[2].volume, 0.1);
+        backend.clone().with_default_for(Role::Console);
+        monitor_step(&services, &watcher, &mut devices, Duration::ZERO, true).unwrap();
+        assert_eq!(backend.devices()[2].volume, 0.8);
+        assert_eq!(backend.devices()[0].volume, 0.2);
+    }
+
+    #[test]
+    fn test_volume_steps() {
+        let backend = MockBackend::new().with_device("Mic", 0.5, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let step = |change: &str| Commands::Volume {
+            level: Some(change.parse().unwrap()),
+            db: None,
+            device: None,
+        };
+
+        execute(step("+5"), true, &services).unwrap();
+        assert!((backend.devices()[0].volume - 0.55).abs() < 1e-6);
+        execute(step("-3dB"), true, &services).unwrap();
+        assert!((backend.devices()[0].volume - 0.5).abs() < 1e-6);
+
+        // Steps stop at the ends of the range
+        execute(step("+80"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 1.0);
+        execute(step("-100dB"), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.0);
+    }
+
+    #[test]
+    fn test_volume_db() {
+        let backend = MockBackend::new().with_device("Mic", 0.3, false);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+        let set_db = |db: f32| Commands::Volume {
+            level: None,
+            db: Some(db),
+            device: None,
+        };
+
+        execute(set_db(-15.0), true, &services).unwrap();
+        assert_eq!(backend.devices()[0].volume, 0.75);
+
+        // Gains outside the device's range are rejected
+        assert!(execute(set_db(12.0), true, &services).is_err());
+        assert_eq!(backend.devices()[0].volume, 0.75);
+
+        let get = Commands::Volume {
+            level: None,
+            db: None,
+            device: None,
+        };
+        execute(get, false, &services).unwrap();
+    }
+
+    #[test]
+    fn test_install_db_target() {
+        let scheduler = MockScheduler::new();
+        let (services, _dir) = mock_services(&MockBackend::new(), &scheduler);
+
+        let install = Commands::Install {
+            volume: VolumeLevel::Scalar(0.95),
+            db: Some(-12.5),
+            interval: 5,
+            device: None,
+        };
+        execute(install, true, &services).unwrap();
+        assert_eq!(scheduler.task().unwrap().target, VolumeLevel::Db(-12.5));
+        let config = Config::load(&services.config_path().unwrap()).unwrap();
+        assert_eq!(config.target_volume, VolumeLevel::Db(-12.5));
+
+        // A dB target saved by an older version is replaced
+        std::fs::write(services.config_path().unwrap(), "target_db = -6.0\n").unwrap();
+        let install = Commands::Install {
+            volume: "80%".parse().unwrap(),
+            db: None,
+            interval: 5,
+            device: None,
+        };
+        execute(install, true, &services).unwrap();
+        assert_eq!(scheduler.task().unwrap().target, VolumeLevel::Scalar(0.8));
+        let config = Config::load(&services.config_path().unwrap()).unwrap();
+        assert_eq!(config.target_db, None);
+    }
+
+    #[test]
+    fn test_install_and_uninstall() {
+        let scheduler = MockScheduler::new();
+        let (services, _dir) = mock_services(&MockBackend::new(), &scheduler);
+
+        let install = Commands::Install {
+            volume: "0.8".parse().unwrap(),
+            db: None,
+            interval: 10,
+            device: Some("USB".to_string()),
+        };
+        execute(install, true, &services).unwrap();
+
+        let task = scheduler.task().unwrap();
+        assert_eq!(task.target, VolumeLevel::Scalar(0.8));
+        assert_eq!(task.interval_minutes, 10);
+
+        let config = Config::load(&services.config_path().unwrap()).unwrap();
+        assert_eq!(config.target_volume, VolumeLevel::Scalar(0.8));
+        assert_eq!(config.run_interval_minutes, 10);
+        assert_eq!(config.device.as_deref(), Some("USB"));
+
+        // Uninstalling needs neither the config file nor a backend, so a
+        // broken config file does not stand in its way
+        let mut services = services;
+        services.backend = OnceCell::new();
+        std::fs::write(services.config_path().unwrap(), "target_volume = [").unwrap();
+        let get_volume = Commands::Volume {
+            level: None,
+            db: None,
+            device: None,
+        };
+        assert!(execute(get_volume, true, &services).is_err());
+        execute(Commands::Uninstall, true, &services).unwrap();
+        assert!(scheduler.task().is_none());
+    }
+
+    #[test]
+    fn test_install_and_uninstall_dry_run() {
+        let scheduler = MockScheduler::new();
+        let (services, _dir) = dry_run_services(&MockBackend::new(), &scheduler);
+
+        let install = || Commands::Install {
+            volume: "0.8".parse().unwrap(),
+            db: None,
+            interval: 10,
+            device: None,
+        };
+        execute(install(), true, &services).unwrap();
+        assert!(scheduler.task().is_none());
+        assert!(!services.config_path().unwrap().exists());
+
+        let (installed, _dir) = mock_services(&MockBackend::new(), &scheduler);
+        execute(install(), true, &installed).unwrap();
+        execute(Commands::Uninstall, true, &services).unwrap();
+        assert!(scheduler.task().is_some());
+    }
+
+    #[test]
+    fn test_install_keeps_backend_settings() {
+        let (services, _dir) = mock_services(&MockBackend::new(), &MockScheduler::new());
+        let config_path = services.config_path().unwrap();
+        std::fs::write(
+            &config_path,
+            "backend = \"alsa\"\n[alsa]\ncontrol = \"Mic\"\n",
+        )
+        .unwrap();
+
+        let install = Commands::Install {
+            volume: "70".parse().unwrap(),
+            db: None,
+            interval: 5,
+            device: None,
+        };
+        execute(install, true, &services).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.target_volume, VolumeLevel::Scalar(0.7));
+        assert_eq!(config.backend, BackendKind::Alsa);
+        assert_eq!(config.alsa.control.as_deref(), Some("Mic"));
+    }
+
+    #[test]
+    fn test_devices() {
+        let backend = MockBackend::new()
+            .with_device("Built-in", 0.4, false)
+            .with_device("USB Mic", 0.8, true)
+            .with_state(audio::DeviceState::Unplugged);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        execute(Commands::Devices { output: false }, false, &services).unwrap();
+
+        let failing = backend.with_failure(audio::Operation::ListDevices);
+        let (services, _dir) = mock_services(&failing, &MockScheduler::new());
+        assert!(execute(Commands::Devices { output: false }, true, &services).is_err());
+    }
+
+    #[test]
+    fn test_config_tolerates_backend_failures() {
+        let backend = MockBackend::new()
+            .with_device("Mic", 0.3, true)
+            .with_failure(audio::Operation::GetVolume)
+            .with_failure(audio::Operation::ListDevices);
+        let (services, _dir) = mock_services(&backend, &MockScheduler::new());
+
+        execute(Commands::Config, true, &services).unwrap();
+    }
 }
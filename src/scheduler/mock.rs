@@ -0,0 +1,78 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+use super::{Scheduler, TaskPlan};
+use crate::volume::VolumeLevel;
+
+/// Task registered with a `MockScheduler`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisteredTask {
+    pub target: VolumeLevel,
+    pub interval_minutes: u32,
+}
+
+/// In-memory scheduler that records the registered task instead of touching
+/// the system
+///
+/// Clones share state, so a test can keep a handle to inspect the task.
+#[derive(Debug, Clone, Default)]
+pub struct MockScheduler {
+    task: Arc<Mutex<Option<RegisteredTask>>>,
+}
+
+impl MockScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Currently registered task, if any
+    pub fn task(&self) -> Option<RegisteredTask> {
+        *self.task.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_task(&self, task: Option<RegisteredTask>) {
+        *self.task.lock().unwrap_or_else(|e| e.into_inner()) = task;
+    }
+}
+
+impl Scheduler for MockScheduler {
+    fn name(&self) -> &'static str {
+        "mock scheduler"
+    }
+
+    fn register_task(&self, target: VolumeLevel, interval_minutes: u32) -> Result<()> {
+        self.set_task(Some(RegisteredTask {
+            target,
+            interval_minutes,
+        }));
+        Ok(())
+    }
+
+    fn unregister_task(&self) -> Result<()> {
+        self.set_task(None);
+        Ok(())
+    }
+
+    fn is_registered(&self) -> bool {
+        self.task().is_some()
+    }
+
+    fn installed_files(&self) -> Result<Vec<(&'static str, PathBuf)>> {
+        Ok(Vec::new())
+    }
+
+    fn task_name(&self) -> String {
+        "mock task".to_string()
+    }
+
+    fn plan_task(&self, target: VolumeLevel, interval_minutes: u32) -> Result<TaskPlan> {
+        Ok(TaskPlan {
+            command: super::volume_args(target),
+            triggers: vec![format!("every {} minutes", interval_minutes)],
+        })
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+
+use super::{Scheduler, TaskPlan};
+use crate::config;
+use crate::volume::VolumeLevel;
+
+const UNIT_NAME: &str = "mic-volume-control";
+
+/// Periodic volume task backed by a systemd user service and timer
+pub struct TaskScheduler {
+    unit_dir: PathBuf,
+}
+
+impl TaskScheduler {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            unit_dir: Self::get_unit_dir()?,
+        })
+    }
+
+    fn get_unit_dir() -> Result<PathBuf> {
+        let mut path = config::xdg_config_home()?;
+        path.push("systemd");
+        path.push("user");
+
+        Ok(path)
+    }
+
+    fn service_file_name() -> String {
+        format!("{}.service", UNIT_NAME)
+    }
+
+    fn timer_file_name() -> String {
+        format!("{}.timer", UNIT_NAME)
+    }
+
+    /// `ExecStart` command line of the service
+    fn exec_start(exe_path: &Path, target: VolumeLevel) -> Result<String> {
+        let exe_path_str = exe_path
+            .to_str()
+            .context("Failed to convert exe path to string")?;
+
+        // `%` starts a specifier in unit files and must be doubled
+        Ok(format!(
+            "\"{}\" --quiet {}",
+            exe_path_str.replace('%', "%%"),
+            super::volume_args(target)
+        ))
+    }
+
+    fn service_unit(exe_path: &Path, target: VolumeLevel) -> Result<String> {
+        Ok(format!(
+            "[Unit]
+Description=Set microphone volume to configured level
+
+[Service]
+Type=oneshot
+ExecStart={}
+",
+            Self::exec_start(exe_path, target)?
+        ))
+    }
+
+    fn timer_unit(interval_minutes: u32) -> String {
+        // The user manager starts at login, so OnStartupSec mirrors the
+        // delayed logon trigger of the Windows task
+        format!(
+            "[Unit]
+Description=Periodically set microphone volume to configured level
+
+[Timer]
+OnStartupSec=1min
+OnUnitActiveSec={}min
+Unit={}
+
+[Install]
+WantedBy=timers.target
+",
+            interval_minutes,
+            Self::service_file_name()
+        )
+    }
+
+    fn systemctl(args: &[&str]) -> Result<()> {
+        let output = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .output()
+            .context("Failed to run systemctl")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "systemctl exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Scheduler for TaskScheduler {
+    fn name(&self) -> &'static str {
+        "systemd user timer"
+    }
+
+    fn register_task(&self, target: VolumeLevel, interval_minutes: u32) -> Result<()> {
+        let exe_path = std::env::current_exe().context("Failed to get current executable path")?;
+
+        fs::create_dir_all(&self.unit_dir).context("Failed to create systemd unit directory")?;
+
+        fs::write(
+            self.unit_dir.join(TaskScheduler::service_file_name()),
+            TaskScheduler::service_unit(&exe_path, target)?,
+        )
+        .context("Failed to write service unit")?;
+        fs::write(
+            self.unit_dir.join(TaskScheduler::timer_file_name()),
+            TaskScheduler::timer_unit(interval_minutes),
+        )
+        .context("Failed to write timer unit")?;
+
+        TaskScheduler::systemctl(&["daemon-reload"])
+            .context("Failed to reload systemd user units")?;
+        TaskScheduler::systemctl(&["enable", "--now", &TaskScheduler::timer_file_name()])
+            .context("Failed to enable timer")?;
+
+        Ok(())
+    }
+
+    fn unregister_task(&self) -> Result<()> {
+        // The timer may already be stopped or unknown to systemd; the unit files
+        // are removed either way
+        let _ = TaskScheduler::systemctl(&["disable", "--now", &TaskScheduler::timer_file_name()]);
+
+        for file_name in [
+            TaskScheduler::timer_file_name(),
+            TaskScheduler::service_file_name(),
+        ] {
+            let path = self.unit_dir.join(file_name);
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to delete {}", path.display()))?;
+            }
+        }
+
+        TaskScheduler::systemctl(&["daemon-reload"])
+            .context("Failed to reload systemd user units")?;
+
+        Ok(())
+    }
+
+    fn is_registered(&self) -> bool {
+        self.unit_dir
+            .join(TaskScheduler::timer_file_name())
+            .exists()
+    }
+
+    fn installed_files(&self) -> Result<Vec<(&'static str, PathBuf)>> {
+        Ok(vec![
+            (
+                "Service unit",
+                self.unit_dir.join(TaskScheduler::service_file_name()),
+            ),
+            (
+                "Timer unit",
+                self.unit_dir.join(TaskScheduler::timer_file_name()),
+            ),
+        ])
+    }
+
+    fn task_name(&self) -> String {
+        TaskScheduler::timer_file_name()
+    }
+
+    fn plan_task(&self, target: VolumeLevel, interval_minutes: u32) -> Result<TaskPlan> {
+        let exe_path = std::env::current_exe().context("Failed to get current executable path")?;
+
+        Ok(TaskPlan {
+            command: TaskScheduler::exec_start(&exe_path, target)?,
+            triggers: vec![
+                "1 minute after login (OnStartupSec=1min)".to_string(),
+                format!(
+                    "every {} minutes (OnUnitActiveSec={}min)",
+                    interval_minutes, interval_minutes
+                ),
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_unit() {
+        let unit = TaskScheduler::service_unit(
+            Path::new("/opt/mic 100%/mic-volume-control"),
+            VolumeLevel::Scalar(0.8),
+        )
+        .unwrap();
+        assert!(
+            unit.contains("ExecStart=\"/opt/mic 100%%/mic-volume-control\" --quiet volume 0.8")
+        );
+        assert!(unit.contains("Type=oneshot"));
+
+        let unit = TaskScheduler::service_unit(
+            Path::new("/usr/bin/mic-volume-control"),
+            VolumeLevel::Db(-6.0),
+        )
+        .unwrap();
+        assert!(unit.contains("--quiet volume --db -6\n"));
+    }
+
+    #[test]
+    fn test_timer_unit() {
+        let unit = TaskScheduler::timer_unit(10);
+        assert!(unit.contains("OnUnitActiveSec=10min"));
+        assert!(unit.contains("Unit=mic-volume-control.service"));
+        assert!(unit.contains("WantedBy=timers.target"));
+    }
+
+    #[test]
+    fn test_plan_task() {
+        let scheduler = TaskScheduler {
+            unit_dir: PathBuf::from("/home/user/.config/systemd/user"),
+        };
+        let plan = scheduler.plan_task(VolumeLevel::Scalar(0.8), 10).unwrap();
+        assert!(plan.command.ends_with("\" --quiet volume 0.8"));
+        assert_eq!(
+            plan.triggers,
+            [
+                "1 minute after login (OnStartupSec=1min)",
+                "every 10 minutes (OnUnitActiveSec=10min)"
+            ]
+        );
+        assert_eq!(scheduler.task_name(), "mic-volume-control.timer");
+    }
+}
@@ -0,0 +1,387 @@
+use std::time::Duration;
+use std::{fmt, str::FromStr};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Forms accepted for an absolute volume, quoted in parse errors
+const EXPECTED_LEVEL: &str =
+    "expected a percentage (80 or 80%), a fraction (0.8) or a gain in dB (-6dB)";
+
+/// Volume to set: a scalar (0.0 - 1.0) or an absolute gain in dB
+///
+/// Parsed from the same forms on the command line and in the config file:
+/// `80` and `80%` are percentages, `0.8` is a fraction (a number with a
+/// decimal point up to 1) and `-6dB` a gain. In the config file the level is
+/// written as a scalar number, or as a string for a gain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeLevel {
+    Scalar(f32),
+    Db(f32),
+}
+
+impl VolumeLevel {
+    /// Number of the level, in its unit
+    pub fn value(self) -> f32 {
+        match self {
+            VolumeLevel::Scalar(value) | VolumeLevel::Db(value) => value,
+        }
+    }
+
+    /// Level in the same unit with another number
+    pub fn with_value(self, value: f32) -> Self {
+        match self {
+            VolumeLevel::Scalar(_) => VolumeLevel::Scalar(value),
+            VolumeLevel::Db(_) => VolumeLevel::Db(value),
+        }
+    }
+
+    /// Level of a bare number: a fraction when written with a decimal point
+    /// and at most 1, a percentage otherwise
+    fn from_number(number: f64, has_point: bool, text: &str) -> Result<Self> {
+        if has_point && (0.0..=1.0).contains(&number) {
+            return Ok(VolumeLevel::Scalar(number as f32));
+        }
+        Self::from_percent(number, text)
+    }
+
+    fn from_percent(percent: f64, text: &str) -> Result<Self> {
+        if !(0.0..=100.0).contains(&percent) {
+            anyhow::bail!(
+                "Invalid volume '{}': a percentage must be between 0 and 100",
+                text
+            );
+        }
+        Ok(VolumeLevel::Scalar((percent / 100.0) as f32))
+    }
+}
+
+impl FromStr for VolumeLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim();
+        let lower = text.to_ascii_lowercase();
+
+        if let Some(number) = lower.strip_suffix("db") {
+            let db = parse_number(number).with_context(|| {
+                format!(
+                    "Invalid volume '{}': expected a gain in dB such as -6dB",
+                    text
+                )
+            })?;
+            return Ok(VolumeLevel::Db(db as f32));
+        }
+        if text.starts_with('+') {
+            anyhow::bail!(
+                "Invalid volume '{}': steps such as +5 are only accepted by the volume \
+                 command; {}",
+                text,
+                EXPECTED_LEVEL
+            );
+        }
+        if let Some(number) = text.strip_suffix('%') {
+            let percent = parse_number(number).with_context(|| {
+                format!(
+                    "Invalid volume '{}': expected a percentage such as 80%",
+                    text
+                )
+            })?;
+            return Self::from_percent(percent, text);
+        }
+
+        let number = parse_number(text)
+            .with_context(|| format!("Invalid volume '{}': {}", text, EXPECTED_LEVEL))?;
+        Self::from_number(number, text.contains('.'), text)
+    }
+}
+
+impl fmt::Display for VolumeLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeLevel::Scalar(volume) => write!(f, "{}%", format_percent(*volume)),
+            VolumeLevel::Db(db) => write!(f, "{:.2} dB", db),
+        }
+    }
+}
+
+impl Serialize for VolumeLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            VolumeLevel::Scalar(volume) => serializer.serialize_f32(*volume),
+            VolumeLevel::Db(db) => serializer.serialize_str(&format!("{}dB", db)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VolumeLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Integer(i64),
+            Float(f64),
+            Text(String),
+        }
+
+        let level = match Raw::deserialize(deserializer)? {
+            // Config files from before percentages were accepted hold
+            // fractions, where a bare 1 meant 100%; one percent is "1%"
+            Raw::Integer(1) => Ok(VolumeLevel::Scalar(1.0)),
+            Raw::Integer(percent) => Self::from_percent(percent as f64, &percent.to_string()),
+            Raw::Float(number) => Self::from_number(number, true, &number.to_string()),
+            Raw::Text(text) => text.parse(),
+        };
+        level.map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// Volume change requested on the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeChange {
+    /// Set this level
+    To(VolumeLevel),
+    /// Step the current volume by this amount, in percent points or dB
+    By(VolumeLevel),
+}
+
+impl FromStr for VolumeChange {
+    type Err = anyhow::Error;
+
+    /// Parse a level (see `VolumeLevel`), or a step when signed: `+5` or `-5%`
+    /// in percent points, `+1.5dB` or `-3dB` in dB
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim();
+        if !text.starts_with(['+', '-']) {
+            return Ok(VolumeChange::To(text.parse()?));
+        }
+
+        let lower = text.to_ascii_lowercase();
+        if let Some(number) = lower.strip_suffix("db") {
+            let step = parse_number(number).with_context(|| {
+                format!(
+                    "Invalid volume step '{}': expected a step in dB such as +1.5dB or -3dB",
+                    text
+                )
+            })?;
+            return Ok(VolumeChange::By(VolumeLevel::Db(step as f32)));
+        }
+
+        let number = lower.strip_suffix('%').unwrap_or(&lower);
+        let step = parse_number(number).with_context(|| {
+            format!(
+                "Invalid volume step '{}': expected a step in percent such as +5 or -5%",
+                text
+            )
+        })?;
+        Ok(VolumeChange::By(VolumeLevel::Scalar((step / 100.0) as f32)))
+    }
+}
+
+/// Gradual volume change instead of a jump (`[ramp]` in the config file)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ramp {
+    /// Time the whole change takes, in milliseconds
+    pub duration_ms: u64,
+
+    /// How the volume moves over that time
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+impl Ramp {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+impl fmt::Display for Ramp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ms, {}", self.duration_ms, self.curve)
+    }
+}
+
+/// Shape of a ramp: how far along the change the volume is at each point in
+/// time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    /// The same change in every step
+    #[default]
+    Linear,
+    /// Slow at first, fast at the end
+    EaseIn,
+    /// Fast at first, slow at the end
+    EaseOut,
+    /// Slow at both ends
+    EaseInOut,
+}
+
+impl Curve {
+    /// Fraction of the change reached after `progress` (0.0 - 1.0) of the
+    /// ramp's time
+    pub fn at(self, progress: f32) -> f32 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Curve::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Curve::Linear => "linear",
+            Curve::EaseIn => "ease-in",
+            Curve::EaseOut => "ease-out",
+            Curve::EaseInOut => "ease-in-out",
+        })
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Percentage of a scalar with up to four decimals and no trailing zeros
+fn format_percent(volume: f32) -> String {
+    let percent = format!("{:.4}", volume as f64 * 100.0);
+    percent
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(s: &str) -> VolumeLevel {
+        s.parse().unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        format!("{:#}", s.parse::<VolumeLevel>().unwrap_err())
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(level("80"), VolumeLevel::Scalar(0.8));
+        assert_eq!(level("80%"), VolumeLevel::Scalar(0.8));
+        assert_eq!(level(" 12.5 % "), VolumeLevel::Scalar(0.125));
+        assert_eq!(level("0.8"), VolumeLevel::Scalar(0.8));
+        assert_eq!(level("1.0"), VolumeLevel::Scalar(1.0));
+        assert_eq!(level("1"), VolumeLevel::Scalar(0.01));
+        assert_eq!(level("0"), VolumeLevel::Scalar(0.0));
+        assert_eq!(level("-6dB"), VolumeLevel::Db(-6.0));
+        assert_eq!(level("2.5 db"), VolumeLevel::Db(2.5));
+    }
+
+    #[test]
+    fn test_parse_level_errors() {
+        assert!(error("loud").contains("expected a percentage (80 or 80%)"));
+        assert!(error("150").contains("between 0 and 100"));
+        assert!(error("-5%").contains("between 0 and 100"));
+        assert!(error("eighty%").contains("expected a percentage such as 80%"));
+        assert!(error("xdB").contains("expected a gain in dB"));
+        assert!(error("+5").contains("only accepted by the volume command"));
+        assert!(error("inf").contains("expected a percentage"));
+    }
+
+    #[test]
+    fn test_parse_change() {
+        let change = |s: &str| s.parse::<VolumeChange>().unwrap();
+        assert_eq!(change("80"), VolumeChange::To(VolumeLevel::Scalar(0.8)));
+        assert_eq!(change("0.5"), VolumeChange::To(VolumeLevel::Scalar(0.5)));
+        assert_eq!(change("6dB"), VolumeChange::To(VolumeLevel::Db(6.0)));
+        assert_eq!(change("+5"), VolumeChange::By(VolumeLevel::Scalar(0.05)));
+        assert_eq!(change("-10%"), VolumeChange::By(VolumeLevel::Scalar(-0.1)));
+        assert_eq!(change("-3db"), VolumeChange::By(VolumeLevel::Db(-3.0)));
+        assert_eq!(change("+1.5 dB"), VolumeChange::By(VolumeLevel::Db(1.5)));
+
+        for (invalid, expected) in [
+            ("101", "between 0 and 100"),
+            ("loud", "expected a percentage"),
+            ("+", "expected a step in percent"),
+            ("-dB", "expected a step in dB"),
+            ("+inf", "expected a step in percent"),
+        ] {
+            let err = format!("{:#}", invalid.parse::<VolumeChange>().unwrap_err());
+            assert!(err.contains(expected), "{}: {}", invalid, err);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(VolumeLevel::Scalar(0.8).to_string(), "80%");
+        assert_eq!(VolumeLevel::Scalar(1.0 / 3.0).to_string(), "33.3333%");
+        assert_eq!(VolumeLevel::Scalar(0.0).to_string(), "0%");
+        assert_eq!(VolumeLevel::Scalar(1.0).to_string(), "100%");
+        assert_eq!(VolumeLevel::Db(-12.5).to_string(), "-12.50 dB");
+
+        // The displayed form parses back to the same level
+        let third = VolumeLevel::Scalar(1.0 / 3.0);
+        match level(&third.to_string()) {
+            VolumeLevel::Scalar(volume) => assert!((volume - 1.0 / 3.0).abs() < 1e-6),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Wrapper {
+            level: VolumeLevel,
+        }
+
+        let parse = |content: &str| toml::from_str::<Wrapper>(content).map(|w| w.level);
+        assert_eq!(parse("level = 0.95").unwrap(), VolumeLevel::Scalar(0.95));
+        assert_eq!(parse("level = 1.0").unwrap(), VolumeLevel::Scalar(1.0));
+        assert_eq!(parse("level = 80").unwrap(), VolumeLevel::Scalar(0.8));
+        assert_eq!(parse("level = 1").unwrap(), VolumeLevel::Scalar(1.0));
+        assert_eq!(parse("level = \"1%\"").unwrap(), VolumeLevel::Scalar(0.01));
+        assert_eq!(parse("level = 2").unwrap(), VolumeLevel::Scalar(0.02));
+        assert_eq!(parse("level = 62.5").unwrap(), VolumeLevel::Scalar(0.625));
+        assert_eq!(parse("level = \"80%\"").unwrap(), VolumeLevel::Scalar(0.8));
+        assert_eq!(parse("level = \"-6dB\"").unwrap(), VolumeLevel::Db(-6.0));
+        assert!(parse("level = 120").is_err());
+        assert!(parse("level = \"+5\"").is_err());
+
+        for level in [VolumeLevel::Scalar(0.333), VolumeLevel::Db(-12.5)] {
+            let serialized = toml::to_string(&Wrapper { level }).unwrap();
+            assert_eq!(parse(&serialized).unwrap(), level);
+        }
+    }
+
+    #[test]
+    fn test_curves() {
+        for curve in [
+            Curve::Linear,
+            Curve::EaseIn,
+            Curve::EaseOut,
+            Curve::EaseInOut,
+        ] {
+            assert_eq!(curve.at(0.0), 0.0);
+            assert_eq!(curve.at(1.0), 1.0);
+            assert_eq!(curve.at(1.5), 1.0);
+        }
+        assert_eq!(Curve::Linear.at(0.25), 0.25);
+        assert!(Curve::EaseIn.at(0.25) < 0.25);
+        assert!(Curve::EaseOut.at(0.25) > 0.25);
+        assert!(Curve::EaseInOut.at(0.25) < 0.25);
+        assert!(Curve::EaseInOut.at(0.75) > 0.75);
+
+        let ramp: Ramp = toml::from_str("duration_ms = 1500\ncurve = \"ease-in-out\"").unwrap();
+        assert_eq!(ramp.duration(), Duration::from_millis(1500));
+        assert_eq!(ramp.to_string(), "1500 ms, ease-in-out");
+        let ramp: Ramp = toml::from_str("duration_ms = 500").unwrap();
+        assert_eq!(ramp.curve, Curve::Linear);
+        assert!(toml::from_str::<Ramp>("duration_ms = 500\ncurve = \"bounce\"").is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::audio::{DeviceInfo, DeviceState, OWN_WRITE_WINDOW, VolumeBackend, VolumeEvent, Watch};
+
+/// Time to wait for further notifications after one arrived; a slider drag
+/// or a device applying several changes is handled in one go
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Time after notifications of our own writes at which the settings are
+/// checked once more, beyond the window in which the Linux backends take any
+/// change for one of ours
+pub const ECHO_RECHECK: Duration = OWN_WRITE_WINDOW.saturating_add(Duration::from_millis(500));
+
+/// Change notifications of one or more backends, merged into one stream
+pub struct Watcher<'a> {
+    backends: Vec<&'a dyn VolumeBackend>,
+    events: Receiver<VolumeEvent>,
+    _watches: Vec<Watch>,
+}
+
+impl<'a> Watcher<'a> {
+    /// Subscribe to the changes of every backend in `backends`
+    pub fn new(backends: &[&'a dyn VolumeBackend]) -> Result<Self> {
+        let (events, watches) = Self::subscribe(backends)?;
+        Ok(Self {
+            backends: backends.to_vec(),
+            events,
+            _watches: watches,
+        })
+    }
+
+    fn subscribe(backends: &[&dyn VolumeBackend]) -> Result<(Receiver<VolumeEvent>, Vec<Watch>)> {
+        let (sender, events) = mpsc::channel();
+        let watches = backends
+            .iter()
+            .map(|backend| {
+                backend.watch(sender.clone()).with_context(|| {
+                    format!("Failed to watch the {} backend for changes", backend.name())
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        // Only the backends hold senders now, so the channel disconnects
+        // when all of them stopped
+        Ok((events, watches))
+    }
+
+    /// Subscribe again, e.g. to also hear from devices that arrived since;
+    /// pending notifications are dropped
+    pub fn renew(&mut self) -> Result<()> {
+        // Unsubscribe first, so no device is registered twice
+        self._watches.clear();
+        (self.events, self._watches) = Self::subscribe(&self.backends)?;
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for changes, and return them once no more arrive
+    /// for `DEBOUNCE`; empty when none arrived in time
+    pub fn wait(&self, timeout: Duration) -> Result<Vec<VolumeEvent>> {
+        let first = match self.events.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Ok(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Change notifications stopped"),
+        };
+
+        let mut events = vec![first];
+        while let Ok(event) = self.events.recv_timeout(DEBOUNCE) {
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// What `lock` does about an external change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Put the target back right away
+    Restore,
+    /// Another application keeps changing the volume: leave it alone for
+    /// this long, then restore
+    BackOff(Duration),
+}
+
+/// Tells a one-off external change from a tug-of-war with an application
+/// that re-adjusts the volume every time it is restored
+///
+/// `max_changes` changes within `window` are a fight. Every fight backs off
+/// twice as long as the previous one, up to `max_backoff`; once no fight
+/// happened for `max_backoff`, back-off starts over at `backoff`.
+#[derive(Debug)]
+pub struct FightDetector {
+    max_changes: usize,
+    window: Duration,
+    backoff: Duration,
+    max_backoff: Duration,
+    changes: VecDeque<Instant>,
+    next_backoff: Duration,
+    last_fight: Option<Instant>,
+}
+
+impl FightDetector {
+    pub fn new(
+        max_changes: usize,
+        window: Duration,
+        backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        Self {
+            max_changes,
+            window,
+            backoff,
+            max_backoff,
+            changes: VecDeque::new(),
+            next_backoff: backoff,
+            last_fight: None,
+        }
+    }
+
+    /// Note an external change at `now` and decide how to respond
+    pub fn external_change(&mut self, now: Instant) -> Response {
+        if self
+            .last_fight
+            .is_some_and(|fight| now.duration_since(fight) >= self.max_backoff)
+        {
+            self.next_backoff = self.backoff;
+            self.last_fight = None;
+        }
+
+        while self
+            .changes
+            .front()
+            .is_some_and(|&change| now.duration_since(change) > self.window)
+        {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(now);
+        if self.changes.len() < self.max_changes {
+            return Response::Restore;
+        }
+
+        self.changes.clear();
+        self.last_fight = Some(now);
+        let backoff = self.next_backoff;
+        self.next_backoff = (backoff * 2).min(self.max_backoff);
+        Response::BackOff(backoff)
+    }
+}
+
+/// What a batch of notifications calls for, as `EchoFilter` sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// Only echoes of our own writes: nothing for now
+    Skip,
+    /// The re-check after echoes is due: check the targets only if the
+    /// settings moved since the last check left them
+    IfMoved,
+    /// Someone else changed something, a device came or went, or the
+    /// periodic check is due: check the targets
+    Targets,
+}
+
+/// Keeps `watch` and `lock` from answering the notifications of their own
+/// writes
+///
+/// Answering them would write again whenever the result of a write is not
+/// met yet, e.g. while `max_step_per_run` steps towards the target. A batch
+/// made only of our own notifications is skipped. As the Linux backends take
+/// any change within `OWN_WRITE_WINDOW` of one of our writes for ours, the
+/// settings are compared once more after `delay`, with the state `T` the last
+/// check left them in.
+#[derive(Debug)]
+pub struct EchoFilter<T> {
+    delay: Duration,
+    left: Option<T>,
+    recheck: Option<Instant>,
+}
+
+impl<T: PartialEq> EchoFilter<T> {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            left: None,
+            recheck: None,
+        }
+    }
+
+    /// Time to wait for notifications at `now`: `timeout`, or less when the
+    /// re-check is due earlier
+    pub fn timeout(&self, timeout: Duration, now: Instant) -> Duration {
+        match self.recheck {
+            Some(recheck) => timeout.min(recheck.saturating_duration_since(now)),
+            None => timeout,
+        }
+    }
+
+    /// Decide what `events`, received by `now`, call for; an empty batch is
+    /// the re-check or the periodic check
+    pub fn check(&mut self, events: &[VolumeEvent], now: Instant) -> Check {
+        if !events.is_empty() && events.iter().all(VolumeEvent::is_own) {
+            self.recheck = Some(now + self.delay);
+            return Check::Skip;
+        }
+        match self.recheck.take() {
+            Some(recheck) if events.is_empty() && now >= recheck => Check::IfMoved,
+            _ => Check::Targets,
+        }
+    }
+
+    /// Whether `state` differs from what the last check left, or no check
+    /// noted it
+    pub fn moved(&self, state: &T) -> bool {
+        self.left.as_ref() != Some(state)
+    }
+
+    /// Note the state a check left, `None` when it could not be read
+    pub fn settled(&mut self, state: Option<T>) {
+        self.left = state;
+    }
+}
+
+/// Difference between two device lists, as seen by `monitor`
+#[derive(Debug, Default, PartialEq)]
+pub struct DeviceChanges {
+    /// Devices that became active: plugged in, enabled or newly installed
+    pub added: Vec<DeviceInfo>,
+    /// Devices that were active and no longer are
+    pub removed: Vec<DeviceInfo>,
+    /// Active devices that became the default for a role they were not the
+    /// default for
+    pub new_defaults: Vec<DeviceInfo>,
+}
+
+impl DeviceChanges {
+    pub fn between(before: &[DeviceInfo], after: &[DeviceInfo]) -> Self {
+        let active = |devices: &[DeviceInfo], id: &str| {
+            devices
+                .iter()
+                .find(|device| device.id == id && device.state == DeviceState::Active)
+                .cloned()
+        };
+
+        let mut changes = Self::default();
+        for device in after.iter().filter(|d| d.state == DeviceState::Active) {
+            match active(before, &device.id) {
+                None => changes.added.push(device.clone()),
+                Some(old) => {
+                    if device
+                        .default_for
+                        .iter()
+                        .any(|r| !old.default_for.contains(r))
+                    {
+                        changes.new_defaults.push(device.clone());
+                    }
+                }
+            }
+        }
+        for device in before.iter().filter(|d| d.state == DeviceState::Active) {
+            if active(after, &device.id).is_none() {
+                changes.removed.push(device.clone());
+            }
+        }
+        changes
+    }
+
+    /// IDs of the devices that need their targets applied: the arrived ones
+    /// and the new defaults
+    pub fn to_apply(&self) -> impl Iterator<Item = &str> {
+        self.added
+            .iter()
+            .chain(&self.new_defaults)
+            .map(|device| device.id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{Endpoint, MockBackend, Origin, Role};
+
+    #[test]
+    fn test_watcher() {
+        let capture = MockBackend::new().with_device("Mic", 0.5, false);
+        let output = MockBackend::new().with_device("Speakers", 0.5, false);
+        let watcher = Watcher::new(&[&capture, &output]).unwrap();
+        assert!(watcher.wait(Duration::ZERO).unwrap().is_empty());
+
+        capture.set_volume(&Endpoint::Default, 0.6).unwrap();
+        capture.set_mute(&Endpoint::Default, true).unwrap();
+        output.set_volume(&Endpoint::Default, 0.6).unwrap();
+        assert_eq!(watcher.wait(Duration::ZERO).unwrap().len(), 3);
+
+        let mut watcher = watcher;
+        capture.set_volume(&Endpoint::Default, 0.8).unwrap();
+        watcher.renew().unwrap();
+        assert!(watcher.wait(Duration::ZERO).unwrap().is_empty());
+        output.set_volume(&Endpoint::Default, 0.8).unwrap();
+        assert_eq!(watcher.wait(Duration::ZERO).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_fight_detector() {
+        let second = Duration::from_secs(1);
+        let mut detector = FightDetector::new(3, 10 * second, 5 * second, 20 * second);
+        let start = Instant::now();
+        let at = |seconds: u32| start + second * seconds;
+
+        // Changes spread out are restored one by one
+        assert_eq!(detector.external_change(at(0)), Response::Restore);
+        assert_eq!(detector.external_change(at(8)), Response::Restore);
+        assert_eq!(detector.external_change(at(16)), Response::Restore);
+
+        // The third change within ten seconds is a fight, and every fight in a
+        // row backs off longer
+        assert_eq!(
+            detector.external_change(at(17)),
+            Response::BackOff(5 * second)
+        );
+        for (round, backoff) in [(1, 10), (2, 20), (3, 20)] {
+            let t = 17 + round * 6;
+            assert_eq!(detector.external_change(at(t)), Response::Restore);
+            assert_eq!(detector.external_change(at(t + 1)), Response::Restore);
+            assert_eq!(
+                detector.external_change(at(t + 2)),
+                Response::BackOff(second * backoff)
+            );
+        }
+
+        // After a calm period the back-off starts over
+        assert_eq!(detector.external_change(at(100)), Response::Restore);
+        assert_eq!(detector.external_change(at(101)), Response::Restore);
+        assert_eq!(
+            detector.external_change(at(102)),
+            Response::BackOff(5 * second)
+        );
+    }
+
+    #[test]
+    fn test_echo_filter() {
+        let second = Duration::from_secs(1);
+        let mut echoes = EchoFilter::new(2 * second);
+        let start = Instant::now();
+        let own = [VolumeEvent::VolumeChanged {
+            device: None,
+            origin: Origin::Own,
+        }];
+        let external = VolumeEvent::VolumeChanged {
+            device: None,
+            origin: Origin::External,
+        };
+        assert_eq!(echoes.timeout(60 * second, start), 60 * second);
+        assert_eq!(echoes.check(&[], start), Check::Targets);
+        assert!(echoes.moved(&50));
+
+        // Echoes wait for the re-check, which only acts on a moved state
+        echoes.settled(Some(50));
+        assert_eq!(echoes.check(&own, start), Check::Skip);
+        assert_eq!(echoes.check(&own, start + second), Check::Skip);
+        assert_eq!(echoes.timeout(60 * second, start + second), 2 * second);
+        assert_eq!(echoes.check(&[], start + 3 * second), Check::IfMoved);
+        assert!(!echoes.moved(&50));
+        assert!(echoes.moved(&30));
+        assert_eq!(echoes.timeout(60 * second, start), 60 * second);
+
+        // Anyone else's change, or the periodic check before the re-check,
+        // checks the targets
+        assert_eq!(
+            echoes.check(&[own[0].clone(), external], start),
+            Check::Targets
+        );
+        assert_eq!(echoes.check(&own, start), Check::Skip);
+        assert_eq!(echoes.check(&[], start + second), Check::Targets);
+        assert_eq!(echoes.check(&[], start + 3 * second), Check::Targets);
+    }
+
+    #[test]
+    fn test_device_changes() {
+        let backend = MockBackend::from_spec("Built-in=50; Webcam=50,unplugged").unwrap();
+        let before = backend.list_devices().unwrap();
+        let after = backend
+            .clone()
+            .with_device("USB Headset", 0.3, false)
+            .with_default_for(Role::Communications)
+            .list_devices()
+            .unwrap();
+
+        let changes = DeviceChanges::between(&before, &after);
+        let names = |devices: &[DeviceInfo]| -> Vec<String> {
+            devices.iter().map(|device| device.name.clone()).collect()
+        };
+        assert_eq!(names(&changes.added), ["USB Headset"]);
+        assert!(changes.removed.is_empty());
+        assert!(changes.new_defaults.is_empty());
+        assert_eq!(changes.to_apply().collect::<Vec<_>>(), ["mock-2"]);
+
+        // The headset is unplugged, and the built-in microphone becomes the
+        // communications default again
+        let later = backend
+            .clone()
+            .with_state(DeviceState::Unplugged)
+            .list_devices()
+            .unwrap();
+        let changes = DeviceChanges::between(&after, &later);
+        assert!(changes.added.is_empty());
+        assert_eq!(names(&changes.removed), ["USB Headset"]);
+        assert!(changes.new_defaults.is_empty());
+
+        let mut restored = later.clone();
+        restored[0].default_for.push(Role::Communications);
+        let changes = DeviceChanges::between(&later, &restored);
+        assert_eq!(names(&changes.new_defaults), ["Built-in"]);
+        assert_eq!(changes.to_apply().collect::<Vec<_>>(), ["mock-0"]);
+        assert_eq!(
+            DeviceChanges::between(&later, &later),
+            DeviceChanges::default()
+        );
+    }
+}